@@ -0,0 +1,532 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::Context as _;
+use futures::future::Either;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use tonic::Status;
+
+use crate::errors::{self, reasons, ErrorDetails, StatusExt};
+
+fn env_path(key: &str) -> Option<PathBuf> {
+    std::env::var_os(key).map(PathBuf::from)
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    serde_json::from_reader(std::io::BufReader::new(file))
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+// MARK: Caller
+
+/// How a [`Caller`] proved its identity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Credential {
+    ApiKey,
+    Jwt,
+}
+
+/// Authenticated identity of the caller.
+///
+/// Inserted into the request extensions by [`AuthLayer`] when the request carries a
+/// valid `authorization: Bearer` token.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    subject: String,
+    roles: HashSet<String>,
+    credential: Credential,
+}
+
+impl Caller {
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn roles(&self) -> impl Iterator<Item = &str> {
+        self.roles.iter().map(String::as_str)
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.contains(role)
+    }
+
+    pub fn credential(&self) -> Credential {
+        self.credential
+    }
+}
+
+impl fmt::Display for Caller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.subject)
+    }
+}
+
+// MARK: Authenticator
+
+#[derive(Debug, serde::Deserialize)]
+struct ApiKeyEntry {
+    subject: String,
+    #[serde(default)]
+    roles: HashSet<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(default)]
+    roles: HashSet<String>,
+    /// Space separated, as in OAuth 2.0 access tokens.
+    #[serde(default)]
+    scope: String,
+}
+
+struct Jwt {
+    key: DecodingKey,
+    algorithm: Algorithm,
+    validation: Validation,
+}
+
+/// Validates bearer tokens against static API keys and locally verified JWTs.
+#[derive(Default)]
+pub struct Authenticator {
+    api_keys: HashMap<String, ApiKeyEntry>,
+    jwt: Option<Jwt>,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Authenticator")
+            .field("api_keys", &self.api_keys.len())
+            .field("jwt", &self.jwt.as_ref().map(|j| j.algorithm))
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .finish()
+    }
+}
+
+impl Authenticator {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Accepts `key` as a token for `subject` with `roles`.
+    pub fn api_key(
+        mut self,
+        key: impl Into<String>,
+        subject: impl Into<String>,
+        roles: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let entry = ApiKeyEntry {
+            subject: subject.into(),
+            roles: roles.into_iter().map(Into::into).collect(),
+        };
+        self.api_keys.insert(key.into(), entry);
+        self
+    }
+
+    /// Reads API keys from a JSON object mapping each key to
+    /// `{"subject": ..., "roles": [...]}`.
+    pub fn api_keys_file(mut self, path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let keys: HashMap<String, ApiKeyEntry> = read_json(path.as_ref())?;
+        self.api_keys.extend(keys);
+        Ok(self)
+    }
+
+    /// Accepts JWTs signed with `algorithm` by the PEM encoded public key in `path`.
+    ///
+    /// The `exp` claim is required, while `iss` and `aud` are only required when
+    /// [`Self::jwt_issuer`] and [`Self::jwt_audience`] are set. Roles are taken from
+    /// the `roles` array and the space separated `scope` claims.
+    pub fn jwt_public_key(
+        self,
+        path: impl AsRef<Path>,
+        algorithm: Algorithm,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let pem = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let key = match algorithm {
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512 => DecodingKey::from_rsa_pem(&pem),
+            Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(&pem),
+            Algorithm::EdDSA => DecodingKey::from_ed_pem(&pem),
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                anyhow::bail!("{algorithm:?} is not a public key algorithm")
+            }
+        }
+        .with_context(|| format!("Invalid {algorithm:?} public key in {}", path.display()))?;
+        let jwt = Jwt {
+            key,
+            algorithm,
+            validation: Validation::new(algorithm),
+        };
+        Ok(Self {
+            jwt: Some(jwt),
+            ..self
+        }
+        .validating())
+    }
+
+    /// Only accepts JWTs whose `iss` claim is `issuer`.
+    pub fn jwt_issuer(self, issuer: &str) -> Self {
+        Self {
+            issuer: Some(issuer.to_string()),
+            ..self
+        }
+        .validating()
+    }
+
+    /// Only accepts JWTs whose `aud` claim contains `audience`.
+    pub fn jwt_audience(self, audience: &str) -> Self {
+        Self {
+            audience: Some(audience.to_string()),
+            ..self
+        }
+        .validating()
+    }
+
+    /// Applies the issuer and audience to the JWT validation, whatever the order of
+    /// the builder calls.
+    fn validating(mut self) -> Self {
+        if let Some(jwt) = &mut self.jwt {
+            let mut validation = Validation::new(jwt.algorithm);
+            // Tokens of identity providers usually have an audience, ignored unless
+            // one is expected.
+            validation.validate_aud = false;
+            let mut required = vec!["exp"];
+            if let Some(issuer) = &self.issuer {
+                validation.set_issuer(&[issuer]);
+                required.push("iss");
+            }
+            if let Some(audience) = &self.audience {
+                validation.set_audience(&[audience]);
+                validation.validate_aud = true;
+                required.push("aud");
+            }
+            validation.set_required_spec_claims(&required);
+            jwt.validation = validation;
+        }
+        self
+    }
+
+    /// Reads `AUTH_API_KEYS`, `AUTH_JWT_PUBLIC_KEY`, `AUTH_JWT_ALGORITHM`,
+    /// `AUTH_JWT_ISSUER` and `AUTH_JWT_AUDIENCE`.
+    ///
+    /// Returns `None` when neither `AUTH_API_KEYS` nor `AUTH_JWT_PUBLIC_KEY` is set,
+    /// meaning every caller is accepted.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let api_keys = env_path("AUTH_API_KEYS");
+        let public_key = env_path("AUTH_JWT_PUBLIC_KEY");
+        if api_keys.is_none() && public_key.is_none() {
+            return Ok(None);
+        }
+        let mut auth = Self::new();
+        if let Some(path) = api_keys {
+            auth = auth.api_keys_file(path)?;
+        }
+        if let Some(path) = public_key {
+            let algorithm = match std::env::var("AUTH_JWT_ALGORITHM") {
+                Ok(v) => v
+                    .parse()
+                    .with_context(|| format!("Unknown AUTH_JWT_ALGORITHM value {v}"))?,
+                Err(_) => Algorithm::RS256,
+            };
+            auth = auth.jwt_public_key(path, algorithm)?;
+            if let Ok(issuer) = std::env::var("AUTH_JWT_ISSUER") {
+                auth = auth.jwt_issuer(&issuer);
+            }
+            if let Ok(audience) = std::env::var("AUTH_JWT_AUDIENCE") {
+                auth = auth.jwt_audience(&audience);
+            }
+        }
+        Ok(Some(auth))
+    }
+
+    /// Authenticates the `authorization` header, if any.
+    pub fn authenticate(&self, headers: &http::HeaderMap) -> Result<Option<Caller>, Status> {
+        let Some(value) = headers.get(http::header::AUTHORIZATION) else {
+            return Ok(None);
+        };
+        let token = value
+            .to_str()
+            .ok()
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| {
+                unauthenticated("Expected a Bearer token", reasons::MALFORMED_CREDENTIALS)
+            })?
+            .trim();
+        if let Some(entry) = self.api_keys.get(token) {
+            return Ok(Some(Caller {
+                subject: entry.subject.clone(),
+                roles: entry.roles.clone(),
+                credential: Credential::ApiKey,
+            }));
+        }
+        let Some(jwt) = &self.jwt else {
+            return Err(unauthenticated(
+                "Invalid API key",
+                reasons::INVALID_CREDENTIALS,
+            ));
+        };
+        let claims = jsonwebtoken::decode::<Claims>(token, &jwt.key, &jwt.validation)
+            .map_err(|e| {
+                tracing::debug!(error = &e as &dyn std::error::Error, "Rejected token");
+                unauthenticated("Invalid token", reasons::INVALID_CREDENTIALS)
+            })?
+            .claims;
+        let mut roles = claims.roles;
+        roles.extend(claims.scope.split_whitespace().map(str::to_string));
+        Ok(Some(Caller {
+            subject: claims.sub,
+            roles,
+            credential: Credential::Jwt,
+        }))
+    }
+}
+
+// MARK: Policy
+
+/// Per-method authorization rules.
+///
+/// Methods are named `package.Service/Method`. A pattern is either a method name,
+/// `package.Service/*` for every method of a service, or `*` for every method.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Policy {
+    /// Methods anyone may call, authenticated or not.
+    #[serde(default)]
+    public: Vec<String>,
+    /// Methods each role may call.
+    #[serde(default)]
+    roles: HashMap<String, Vec<String>>,
+}
+
+fn matches(pattern: &str, method: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => method.starts_with(prefix),
+        None => pattern == method,
+    }
+}
+
+impl Policy {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Lets anyone call the methods matching `pattern`.
+    pub fn public(mut self, pattern: impl Into<String>) -> Self {
+        self.public.push(pattern.into());
+        self
+    }
+
+    /// Lets callers with `role` call the methods matching `pattern`.
+    pub fn allow(mut self, role: impl Into<String>, pattern: impl Into<String>) -> Self {
+        self.roles
+            .entry(role.into())
+            .or_default()
+            .push(pattern.into());
+        self
+    }
+
+    /// Adds the rules of `other`, for servers hosting several services.
+    pub fn merge(mut self, other: Self) -> Self {
+        self.public.extend(other.public);
+        for (role, patterns) in other.roles {
+            self.roles.entry(role).or_default().extend(patterns);
+        }
+        self
+    }
+
+    /// Reads the policy from the JSON file at `AUTH_POLICY`, or returns `default`.
+    ///
+    /// The file has the shape `{"public": [pattern...], "roles": {role: [pattern...]}}`.
+    pub fn from_env_or(default: Self) -> anyhow::Result<Self> {
+        Ok(Self::from_env()?.unwrap_or(default))
+    }
+
+    /// Reads the policy from the JSON file at `AUTH_POLICY`, if set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        env_path("AUTH_POLICY")
+            .map(|path| read_json(&path))
+            .transpose()
+    }
+
+    pub fn is_public(&self, method: &str) -> bool {
+        self.public.iter().any(|p| matches(p, method))
+    }
+
+    /// Whether `caller` may call `method`.
+    pub fn allows(&self, caller: &Caller, method: &str) -> bool {
+        caller.roles.iter().any(|role| {
+            self.roles
+                .get(role)
+                .is_some_and(|patterns| patterns.iter().any(|p| matches(p, method)))
+        })
+    }
+}
+
+/// Outcome of authentication, kept in the request extensions for [`authorize`].
+#[derive(Debug, Clone)]
+struct Authentication {
+    policy: Arc<Policy>,
+    caller: Result<Option<Caller>, Status>,
+}
+
+impl Authentication {
+    fn authorize(&self, method: &str) -> Result<(), Status> {
+        if self.policy.is_public(method) {
+            return Ok(());
+        }
+        let caller = self.caller.as_ref().map_err(Clone::clone)?;
+        let Some(caller) = caller else {
+            return Err(unauthenticated(
+                "Missing authorization token",
+                reasons::MISSING_CREDENTIALS,
+            ));
+        };
+        if self.policy.allows(caller, method) {
+            return Ok(());
+        }
+        tracing::info!(%caller, method, "Permission denied");
+        let metadata = HashMap::from([
+            ("method".to_string(), method.to_string()),
+            ("subject".to_string(), caller.subject.clone()),
+        ]);
+        let details =
+            ErrorDetails::with_error_info(reasons::METHOD_NOT_ALLOWED, errors::DOMAIN, metadata);
+        Err(Status::with_error_details(
+            tonic::Code::PermissionDenied,
+            format!("{caller} may not call {method}"),
+            details,
+        ))
+    }
+}
+
+fn unauthenticated(message: &str, reason: &str) -> Status {
+    let details = ErrorDetails::with_error_info(reason, errors::DOMAIN, HashMap::new());
+    Status::with_error_details(tonic::Code::Unauthenticated, message, details)
+}
+
+/// Checks that the caller of a request that went through [`AuthLayer`] may call
+/// `method`.
+///
+/// Handlers serving a gRPC method over plain HTTP call this themselves, behind a
+/// [deferred](AuthLayer::deferred) layer. Always succeeds when authentication is
+/// disabled.
+pub fn authorize(extensions: &http::Extensions, method: &str) -> Result<(), Status> {
+    match extensions.get::<Authentication>() {
+        Some(auth) => auth.authorize(method),
+        None => Ok(()),
+    }
+}
+
+// MARK: Layer
+
+/// Authenticates bearer tokens and enforces a [`Policy`] on every request, taking
+/// its path as the gRPC method.
+///
+/// Requests the caller may not make are rejected with `UNAUTHENTICATED` or
+/// `PERMISSION_DENIED` before reaching the service, whatever their content type:
+/// tonic serves any request routed to it. [Deferred](Self::deferred) layers let
+/// requests through with the outcome recorded for [`authorize`] instead. The
+/// [`Caller`] is inserted into the request extensions in both cases.
+#[derive(Debug, Clone)]
+pub struct AuthLayer {
+    authenticator: Arc<Authenticator>,
+    policy: Arc<Policy>,
+    deferred: bool,
+}
+
+impl AuthLayer {
+    pub fn new(authenticator: Authenticator, policy: Policy) -> Self {
+        Self {
+            authenticator: Arc::new(authenticator),
+            policy: Arc::new(policy),
+            deferred: false,
+        }
+    }
+
+    /// Lets every request through, for HTTP routes whose handlers call [`authorize`]
+    /// with the gRPC method they serve.
+    pub fn deferred(self) -> Self {
+        Self {
+            deferred: true,
+            ..self
+        }
+    }
+
+    /// Builds the layer from [`Authenticator::from_env`] and
+    /// [`Policy::from_env_or`]`(default_policy)`.
+    ///
+    /// Returns `None` when authentication is not configured.
+    pub fn from_env(default_policy: Policy) -> anyhow::Result<Option<Self>> {
+        let Some(authenticator) = Authenticator::from_env()? else {
+            return Ok(None);
+        };
+        let policy = Policy::from_env_or(default_policy)?;
+        tracing::info!(?authenticator, "Authentication enabled");
+        Ok(Some(Self::new(authenticator, policy)))
+    }
+}
+
+impl<S> tower::Layer<S> for AuthLayer {
+    type Service = AuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthService<S> {
+    inner: S,
+    layer: AuthLayer,
+}
+
+impl<S, ReqB, ResB> tower::Service<http::Request<ReqB>> for AuthService<S>
+where
+    S: tower::Service<http::Request<ReqB>, Response = http::Response<ResB>>,
+    ResB: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<S::Future, std::future::Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<ReqB>) -> Self::Future {
+        let auth = Authentication {
+            policy: self.layer.policy.clone(),
+            caller: self.layer.authenticator.authenticate(req.headers()),
+        };
+        if !self.layer.deferred {
+            let method = req.uri().path().trim_start_matches('/');
+            if let Err(status) = auth.authorize(method) {
+                let (parts, _) = status.into_http().into_parts();
+                let res = http::Response::from_parts(parts, ResB::default());
+                return Either::Right(std::future::ready(Ok(res)));
+            }
+        }
+        if let Ok(Some(caller)) = &auth.caller {
+            req.extensions_mut().insert(caller.clone());
+        }
+        req.extensions_mut().insert(auth);
+        Either::Left(self.inner.call(req))
+    }
+}
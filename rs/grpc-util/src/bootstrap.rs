@@ -0,0 +1,349 @@
+use std::time::Duration;
+
+use tonic::server::NamedService;
+use tonic_health::server::HealthReporter;
+use tower::{ServiceBuilder, ServiceExt as _};
+use tower_http::trace::TraceLayer;
+
+use crate::{
+    auth::{AuthLayer, Authenticator, Policy},
+    compression::Compression,
+    context::{MakeRequestSpan, RequestContextLayer},
+    limit::LimitLayer,
+    metrics::MetricsLayer,
+    routes::GrpcRouter,
+    serve::Server,
+    shutdown::Shutdown,
+    transport::{Http2Config, MessageSize},
+};
+
+type AddService = Box<dyn FnOnce(GrpcRouter) -> GrpcRouter + Send>;
+
+/// Serves gRPC services, and optionally HTTP routes, on one port with the layers
+/// every server of the workspace shares.
+///
+/// Requests go through [`RequestContextLayer`], CORS, then either the gRPC side
+/// (gRPC-Web translation, metrics, authentication and limits) or the HTTP side
+/// (authentication and limits), as told by
+/// [`GrpcRouter::handles`].
+///
+/// ```ignore
+/// let bootstrap = Bootstrap::from_env()?;
+/// let greeter = bootstrap.compression().for_service(SERVICE_NAME).apply(
+///     GreeterServer::new(MyGreeter),
+///     GreeterServer::send_compressed,
+///     GreeterServer::accept_compressed,
+/// );
+/// bootstrap
+///     .add_service(greeter, FILE_DESCRIPTOR_SET)
+///     .policy(default_policy())
+///     .serve()
+///     .await?;
+/// ```
+pub struct Bootstrap {
+    shutdown: Shutdown,
+    grace_period: Duration,
+    health_reporter: HealthReporter,
+    health_service: AddService,
+    health: bool,
+    reflection: bool,
+    admin: bool,
+    compression: Compression,
+    message_size: MessageSize,
+    http2: Http2Config,
+    grpc: GrpcRouter,
+    service_names: Vec<&'static str>,
+    file_descriptor_sets: Vec<&'static [u8]>,
+    http: Option<axum::Router>,
+    policy: Policy,
+    policy_override: Option<Policy>,
+    authenticator: Option<Authenticator>,
+    limit: LimitLayer,
+    cors: Option<tower_http::cors::CorsLayer>,
+}
+
+impl std::fmt::Debug for Bootstrap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bootstrap")
+            .field("service_names", &self.service_names)
+            .field("health", &self.health)
+            .field("reflection", &self.reflection)
+            .field("admin", &self.admin)
+            .field("http", &self.http.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Bootstrap {
+    /// Health checking only, without authentication, limits or CORS.
+    pub fn new() -> Self {
+        let (health_reporter, health_service) = tonic_health::server::health_reporter();
+        Self {
+            shutdown: Shutdown::new(),
+            grace_period: crate::shutdown::DEFAULT_GRACE_PERIOD,
+            health_reporter,
+            health_service: Box::new(move |router| router.add_service(health_service)),
+            health: true,
+            reflection: false,
+            admin: false,
+            compression: Compression::new(),
+            message_size: MessageSize::new(),
+            http2: Http2Config::new(),
+            grpc: GrpcRouter::new(),
+            service_names: Vec::new(),
+            file_descriptor_sets: Vec::new(),
+            http: None,
+            policy: Policy::new(),
+            policy_override: None,
+            authenticator: None,
+            limit: LimitLayer::default(),
+            cors: None,
+        }
+    }
+
+    /// Triggers shutdown on signals, and reads the grace period, reflection,
+    /// compression, message size, HTTP/2, authentication, limit and CORS settings from
+    /// the environment.
+    ///
+    /// `AUTH_POLICY`, if set, replaces the policies given to [`Self::policy`].
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            shutdown: Shutdown::new().trigger_on_signal(),
+            grace_period: crate::shutdown::grace_period_from_env()?,
+            reflection: crate::routes::reflection_enabled_from_env()?,
+            compression: Compression::from_env()?,
+            message_size: MessageSize::from_env()?,
+            http2: Http2Config::from_env()?,
+            policy_override: Policy::from_env()?,
+            authenticator: Authenticator::from_env()?,
+            limit: LimitLayer::from_env()?,
+            cors: crate::cors::layer_from_env()?,
+            ..Self::new()
+        })
+    }
+
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
+    /// Reporter of the statuses served by `grpc.health.v1.Health`.
+    pub fn health_reporter(&self) -> HealthReporter {
+        self.health_reporter.clone()
+    }
+
+    /// Compression to apply to the added services, see [`Compression::for_service`].
+    pub fn compression(&self) -> &Compression {
+        &self.compression
+    }
+
+    /// Message size limits to apply to the added services.
+    pub fn message_size(&self) -> MessageSize {
+        self.message_size
+    }
+
+    /// Adds a gRPC service described by `file_descriptor_set` for server reflection.
+    ///
+    /// The service is reported as not serving once shutdown is triggered; setting its
+    /// status beforehand is up to the caller.
+    pub fn add_service<S>(mut self, service: S, file_descriptor_set: &'static [u8]) -> Self
+    where
+        S: NamedService
+            + tower::Service<
+                http::Request<axum::body::Body>,
+                Response = http::Response<tonic::body::BoxBody>,
+                Error = std::convert::Infallible,
+            > + Clone
+            + Send
+            + Sync
+            + 'static,
+        S::Future: Send,
+    {
+        self.service_names.push(S::NAME);
+        self.file_descriptor_sets.push(file_descriptor_set);
+        Self {
+            grpc: self.grpc.add_service(service),
+            ..self
+        }
+    }
+
+    /// Adds the rules of `policy` to the default authorization policy, which applies
+    /// unless `AUTH_POLICY` is set.
+    pub fn policy(self, policy: Policy) -> Self {
+        Self {
+            policy: self.policy.merge(policy),
+            ..self
+        }
+    }
+
+    /// Adds HTTP routes, which authorize requests themselves, see
+    /// [`crate::auth::authorize`].
+    pub fn http(self, router: axum::Router) -> Self {
+        let http = match self.http {
+            Some(http) => http.merge(router),
+            None => router,
+        };
+        Self {
+            http: Some(http),
+            ..self
+        }
+    }
+
+    /// Authenticates callers with `authenticator`, or lets anyone call anything when
+    /// `None`.
+    pub fn authenticator(self, authenticator: Option<Authenticator>) -> Self {
+        Self {
+            authenticator,
+            ..self
+        }
+    }
+
+    pub fn limit(self, limit: LimitLayer) -> Self {
+        Self { limit, ..self }
+    }
+
+    /// Serves `grpc.health.v1.Health`, enabled by default.
+    pub fn health(self, enabled: bool) -> Self {
+        Self {
+            health: enabled,
+            ..self
+        }
+    }
+
+    /// Serves `grpc.reflection.v1` and `grpc.reflection.v1alpha`.
+    pub fn reflection(self, enabled: bool) -> Self {
+        Self {
+            reflection: enabled,
+            ..self
+        }
+    }
+
+    /// Serves `/metrics` on the HTTP side, in addition to `METRICS_PORT` if set.
+    pub fn admin(self, enabled: bool) -> Self {
+        Self {
+            admin: enabled,
+            ..self
+        }
+    }
+
+    /// Binds the [`LISTEN_ADDRS`](crate::serve::listen_addrs_from_env), with TLS if
+    /// configured and the HTTP/2 settings, and serves until shutdown, along with the
+    /// `METRICS_PORT` listener if set.
+    pub async fn serve(self) -> anyhow::Result<()> {
+        let addrs = crate::serve::listen_addrs_from_env()?;
+        let tls = crate::tls::ServerTlsConfig::from_env()?
+            .map(|c| c.build())
+            .transpose()?;
+        let server = Server::bind_all(&addrs).await?.tls(tls).http2(self.http2);
+        for addr in server.listen_addrs() {
+            tracing::info!(%addr, tls = server.is_tls(), "listening");
+        }
+        if let Some(port) = crate::metrics::port_from_env()? {
+            crate::metrics::spawn_listener(port, self.shutdown.clone()).await?;
+        }
+        self.serve_on(server).await
+    }
+
+    /// Serves on `server` until shutdown, e.g. on an ephemeral port or
+    /// [in memory](Server::in_memory) in tests.
+    pub async fn serve_on(self, server: Server) -> anyhow::Result<()> {
+        let Self {
+            shutdown,
+            grace_period,
+            health_reporter,
+            health_service,
+            health,
+            reflection,
+            admin,
+            compression: _,
+            message_size: _,
+            http2: _,
+            mut grpc,
+            service_names,
+            file_descriptor_sets,
+            http,
+            policy,
+            policy_override,
+            authenticator,
+            limit,
+            cors,
+        } = self;
+
+        crate::health::not_serving_on_shutdown(&health_reporter, &shutdown, service_names);
+        if health {
+            grpc = health_service(grpc);
+        }
+        if reflection {
+            grpc = grpc.add_reflection(&file_descriptor_sets)?;
+        }
+        // Metrics name the methods served, and only those.
+        let mut served = file_descriptor_sets.clone();
+        if health {
+            served.push(tonic_health::pb::FILE_DESCRIPTOR_SET);
+        }
+        if reflection {
+            served.push(tonic_reflection::pb::v1::FILE_DESCRIPTOR_SET);
+            served.push(tonic_reflection::pb::v1alpha::FILE_DESCRIPTOR_SET);
+        }
+        let metrics = MetricsLayer::new().with_file_descriptor_sets(&served)?;
+        let auth = authenticator.map(|authenticator| {
+            tracing::info!(?authenticator, "Authentication enabled");
+            AuthLayer::new(authenticator, policy_override.unwrap_or(policy))
+        });
+        let steering = grpc.clone();
+        // `GrpcWebLayer` translates `application/grpc-web(-text)` requests, including
+        // HTTP/1.1 ones, and passes plain gRPC requests through.
+        let grpc_service = ServiceBuilder::new()
+            .map_response(|r: http::Response<_>| r.map(tonic::body::boxed))
+            .layer(metrics)
+            .option_layer(auth.clone())
+            // Shared by both sides, so that limits apply across protocols.
+            .layer(limit.clone())
+            .service(grpc.into_router());
+        let grpc_service = ServiceBuilder::new()
+            .layer(TraceLayer::new_for_grpc().make_span_with(MakeRequestSpan))
+            .layer(tonic_web::GrpcWebLayer::new())
+            .service(grpc_service)
+            .map_request(|r: http::Request<axum::body::Body>| r.map(tonic::body::boxed))
+            .map_response(|res| res.map(axum::body::Body::new))
+            .boxed_clone();
+        let mut http_router = http.unwrap_or_default();
+        if admin {
+            http_router = http_router.merge(crate::metrics::router());
+        }
+        let http_service = http_router
+            .layer(
+                ServiceBuilder::new()
+                    .option_layer(auth.map(AuthLayer::deferred))
+                    .layer(limit),
+            )
+            .layer(TraceLayer::new_for_http().make_span_with(MakeRequestSpan))
+            .into_service()
+            .boxed_clone();
+        let router = tower::steer::Steer::new(
+            [grpc_service, http_service],
+            move |req: &http::Request<_>, _: &[_]| {
+                if steering.handles(req) {
+                    0
+                } else {
+                    1
+                }
+            },
+        );
+        // CORS wraps both sides so that preflight requests, which carry no gRPC
+        // content type, are answered too.
+        let router = ServiceBuilder::new()
+            .option_layer(cors)
+            .layer(RequestContextLayer::new())
+            .service(router);
+        server
+            .graceful_shutdown(shutdown, grace_period)
+            .serve(router)
+            .await
+    }
+}
+
+impl Default for Bootstrap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
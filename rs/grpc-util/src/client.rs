@@ -0,0 +1,178 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use tonic::{
+    metadata::AsciiMetadataValue,
+    service::interceptor::InterceptedService,
+    transport::{self, Endpoint},
+};
+
+/// Channel attaching request metadata to every request, see [`RequestMetadata`].
+pub type Channel = InterceptedService<transport::Channel, RequestMetadata>;
+
+/// Server to connect to: `http(s)://host:port`, `host:port` (over TLS when
+/// configured), or `unix:/path` (also `unix:///path`) for a Unix domain socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Uri(String),
+    HostPort(String),
+    Unix(PathBuf),
+}
+
+impl std::str::FromStr for Target {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(path) = s.strip_prefix("unix:") {
+            let path = path.strip_prefix("//").unwrap_or(path);
+            anyhow::ensure!(!path.is_empty(), "Missing Unix socket path in {s}");
+            return Ok(Self::Unix(path.into()));
+        }
+        if s.contains("://") {
+            return Ok(Self::Uri(s.to_string()));
+        }
+        Ok(Self::HostPort(s.to_string()))
+    }
+}
+
+impl Target {
+    /// Reads `GRPC_ENDPOINT`, falling back to `localhost:{PORT}`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("GRPC_ENDPOINT") {
+            Ok(target) => target
+                .parse()
+                .context("failed to parse GRPC_ENDPOINT value"),
+            Err(_) => Ok(Self::HostPort(format!(
+                "localhost:{}",
+                crate::serve::port_from_env()?
+            ))),
+        }
+    }
+}
+
+/// Connects to `localhost:{port}`, see [`connect_to`].
+pub async fn connect(port: u16) -> anyhow::Result<Channel> {
+    connect_to(Target::HostPort(format!("localhost:{port}"))).await
+}
+
+/// Connects to [`Target::from_env`], see [`connect_to`].
+pub async fn connect_from_env() -> anyhow::Result<Channel> {
+    connect_to(Target::from_env()?).await
+}
+
+/// Connects to `target`, over TLS when `TLS_CA_CERT` is set.
+///
+/// See [`crate::tls::client_config_from_env`] for the TLS options,
+/// [`Http2Config::from_env`](crate::transport::Http2Config::from_env) for the HTTP/2
+/// ones and [`BearerToken::from_env`] for authentication.
+pub async fn connect_to(target: Target) -> anyhow::Result<Channel> {
+    let tls = crate::tls::client_config_from_env()?;
+    let http2 = crate::transport::Http2Config::from_env()?;
+    let token = BearerToken::from_env()?;
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let endpoint = match &target {
+        Target::Uri(uri) => uri.clone(),
+        Target::HostPort(host_port) => format!("{scheme}://{host_port}"),
+        // The authority is only sent as `:authority`, TLS names are checked against
+        // `TLS_DOMAIN`.
+        Target::Unix(_) => format!("{scheme}://localhost"),
+    };
+    tracing::info!(?target, %endpoint, "Connecting");
+    let mut endpoint =
+        http2.apply_endpoint(Endpoint::from_shared(endpoint).context("Invalid endpoint")?);
+    if let Some(tls) = tls {
+        endpoint = endpoint
+            .tls_config(tls)
+            .context("Invalid TLS configuration")?;
+    }
+    let channel = match target {
+        #[cfg(unix)]
+        Target::Unix(path) => {
+            let connector = tower::service_fn(move |_: http::Uri| {
+                let path = path.clone();
+                async move {
+                    let stream = tokio::net::UnixStream::connect(path).await?;
+                    Ok::<_, std::io::Error>(hyper_util::rt::TokioIo::new(stream))
+                }
+            });
+            endpoint.connect_with_connector(connector).await
+        }
+        #[cfg(not(unix))]
+        Target::Unix(path) => anyhow::bail!(
+            "Failed to connect to {}: Unix domain sockets aren't supported on this platform",
+            path.display()
+        ),
+        _ => endpoint.connect().await,
+    };
+    let channel = channel.context("Failed to connect")?;
+    Ok(InterceptedService::new(channel, RequestMetadata { token }))
+}
+
+/// Sets the bearer token, if any, and a fresh `x-request-id` and `traceparent` on
+/// outgoing requests that don't have them yet.
+///
+/// When spans are exported, `traceparent` points to the current span so that the
+/// server span joins its trace.
+///
+/// The request ID and trace ID are recorded as the `request_id` and `trace_id` fields
+/// of the current span, when it has them.
+#[derive(Debug, Clone, Default)]
+pub struct RequestMetadata {
+    token: BearerToken,
+}
+
+impl tonic::service::Interceptor for RequestMetadata {
+    fn call(&mut self, request: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        use crate::context::{RequestId, TraceContext, REQUEST_ID_HEADER, TRACEPARENT_HEADER};
+
+        let mut request = self.token.call(request)?;
+        let span = tracing::Span::current();
+        let mut headers = std::mem::take(request.metadata_mut()).into_headers();
+        let request_id = RequestId::from_headers(&headers).unwrap_or_default();
+        let trace = TraceContext::from_headers(&headers)
+            .or_else(|| TraceContext::from_span(&span))
+            .unwrap_or_else(TraceContext::new_root);
+        span.record("request_id", tracing::field::display(&request_id));
+        span.record("trace_id", tracing::field::display(trace.trace_id()));
+        headers.insert(REQUEST_ID_HEADER, request_id.header_value().clone());
+        headers.insert(TRACEPARENT_HEADER, trace.header_value());
+        *request.metadata_mut() = tonic::metadata::MetadataMap::from_headers(headers);
+        Ok(request)
+    }
+}
+
+/// Sets `authorization: Bearer <token>` on outgoing requests.
+#[derive(Debug, Clone, Default)]
+pub struct BearerToken(Option<AsciiMetadataValue>);
+
+impl BearerToken {
+    pub fn new(token: &str) -> anyhow::Result<Self> {
+        let value = format!("Bearer {token}").parse().context("Invalid token")?;
+        Ok(Self(Some(value)))
+    }
+
+    /// Reads `AUTH_TOKEN`, an API key or JWT. Requests carry no token when unset.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("AUTH_TOKEN") {
+            Ok(token) if !token.is_empty() => {
+                Self::new(&token).context("failed to parse AUTH_TOKEN value")
+            }
+            _ => Ok(Self(None)),
+        }
+    }
+}
+
+impl tonic::service::Interceptor for BearerToken {
+    fn call(
+        &mut self,
+        mut request: tonic::Request<()>,
+    ) -> Result<tonic::Request<()>, tonic::Status> {
+        if let Some(value) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", value.clone());
+        }
+        Ok(request)
+    }
+}
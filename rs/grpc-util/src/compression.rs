@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use tonic::codec::CompressionEncoding;
+
+/// Encodings accepted unless configured otherwise.
+const DEFAULT_ACCEPT: [CompressionEncoding; 2] =
+    [CompressionEncoding::Gzip, CompressionEncoding::Zstd];
+
+fn parse_encoding(s: &str) -> anyhow::Result<CompressionEncoding> {
+    match s.trim() {
+        "gzip" => Ok(CompressionEncoding::Gzip),
+        "zstd" => Ok(CompressionEncoding::Zstd),
+        s => anyhow::bail!("Unknown compression encoding {s}"),
+    }
+}
+
+fn parse_send(var: &str, value: &str) -> anyhow::Result<Option<CompressionEncoding>> {
+    match value.trim() {
+        "" | "none" => Ok(None),
+        v => Ok(Some(
+            parse_encoding(v).with_context(|| format!("failed to parse {var} value"))?,
+        )),
+    }
+}
+
+fn parse_accept(var: &str, value: &str) -> anyhow::Result<Vec<CompressionEncoding>> {
+    value
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(parse_encoding)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .with_context(|| format!("failed to parse {var} value"))
+}
+
+/// Suffix of the variables overriding the settings of `service`: its name in
+/// uppercase, with `_` for other characters than letters and digits.
+fn env_suffix(service: &str) -> String {
+    service
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect()
+}
+
+/// `grpc-encoding` settings of a service or client, possibly overridden for some
+/// services, see [`Self::for_service`].
+///
+/// Messages are only sent compressed when the peer advertises the encoding in
+/// `grpc-accept-encoding`, so peers without compression keep working.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    send: Option<CompressionEncoding>,
+    accept: Vec<CompressionEncoding>,
+    /// Settings of services by [`env_suffix`].
+    services: HashMap<String, Compression>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            send: None,
+            accept: DEFAULT_ACCEPT.to_vec(),
+            services: HashMap::new(),
+        }
+    }
+}
+
+impl Compression {
+    /// Accepts gzip and zstd, and sends uncompressed messages.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Compresses sent messages with `encoding`.
+    pub fn send(self, encoding: Option<CompressionEncoding>) -> Self {
+        Self {
+            send: encoding,
+            ..self
+        }
+    }
+
+    /// Accepts only the messages compressed with one of `encodings`.
+    pub fn accept(self, encodings: impl IntoIterator<Item = CompressionEncoding>) -> Self {
+        Self {
+            accept: encodings.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Replaces the settings of the service named `service`, e.g.
+    /// `route_guide.RouteGuide`.
+    pub fn service(mut self, service: &str, compression: Compression) -> Self {
+        let compression = Self {
+            services: HashMap::new(),
+            ..compression
+        };
+        self.services.insert(env_suffix(service), compression);
+        self
+    }
+
+    /// Settings of the service named `service`, which are these unless overridden.
+    pub fn for_service(&self, service: &str) -> Self {
+        match self.services.get(&env_suffix(service)) {
+            Some(compression) => compression.clone(),
+            None => Self {
+                services: HashMap::new(),
+                ..self.clone()
+            },
+        }
+    }
+
+    /// Reads `GRPC_COMPRESSION`, the encoding of sent messages (`gzip`, `zstd` or
+    /// `none`), and `GRPC_ACCEPT_COMPRESSION`, a comma separated list of accepted
+    /// encodings (`gzip,zstd` by default, empty for none).
+    ///
+    /// Both are overridden for a service by the same variables suffixed with its
+    /// name in uppercase, with `_` for other characters than letters and digits, e.g.
+    /// `GRPC_COMPRESSION_ROUTE_GUIDE_ROUTEGUIDE`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut compression = Self::new();
+        if let Ok(v) = std::env::var("GRPC_COMPRESSION") {
+            compression = compression.send(parse_send("GRPC_COMPRESSION", &v)?);
+        }
+        if let Ok(v) = std::env::var("GRPC_ACCEPT_COMPRESSION") {
+            compression = compression.accept(parse_accept("GRPC_ACCEPT_COMPRESSION", &v)?);
+        }
+        let mut services = HashMap::new();
+        let vars = std::env::vars_os().filter_map(|(k, v)| Some((k.into_string().ok()?, v)));
+        for (var, value) in vars {
+            let value = value
+                .into_string()
+                .map_err(|_| anyhow::anyhow!("{var} isn't valid Unicode"))?;
+            let (suffix, send) = match var.strip_prefix("GRPC_COMPRESSION_") {
+                Some(suffix) => (suffix, true),
+                None => match var.strip_prefix("GRPC_ACCEPT_COMPRESSION_") {
+                    Some(suffix) => (suffix, false),
+                    None => continue,
+                },
+            };
+            let service: &mut Self = services
+                .entry(suffix.to_string())
+                .or_insert_with(|| compression.clone());
+            if send {
+                service.send = parse_send(&var, &value)?;
+            } else {
+                service.accept = parse_accept(&var, &value)?;
+            }
+        }
+        compression.services = services;
+        Ok(compression)
+    }
+
+    /// Configures a generated server or client, given its `send_compressed` and
+    /// `accept_compressed` methods.
+    ///
+    /// ```ignore
+    /// let server = compression.apply(
+    ///     GreeterServer::new(greeter),
+    ///     GreeterServer::send_compressed,
+    ///     GreeterServer::accept_compressed,
+    /// );
+    /// ```
+    pub fn apply<T>(
+        &self,
+        target: T,
+        send_compressed: fn(T, CompressionEncoding) -> T,
+        accept_compressed: fn(T, CompressionEncoding) -> T,
+    ) -> T {
+        let target = match self.send {
+            Some(encoding) => send_compressed(target, encoding),
+            None => target,
+        };
+        self.accept
+            .iter()
+            .fold(target, |t, &e| accept_compressed(t, e))
+    }
+}
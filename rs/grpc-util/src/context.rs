@@ -0,0 +1,370 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use http::{HeaderMap, HeaderName, HeaderValue};
+use rand::Rng;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+pub const TRACEPARENT_HEADER: HeaderName = HeaderName::from_static("traceparent");
+
+/// Longest `x-request-id` taken from a request; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+fn hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+/// Parses `N` bytes of lowercase hex, the only case W3C trace context allows.
+fn parse_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    let valid = s.len() == N * 2
+        && s.bytes()
+            .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase());
+    if !valid {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = u8::from_str_radix(s.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+// MARK: RequestId
+
+/// Identifier correlating the logs of a call on the client and the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(HeaderValue);
+
+impl RequestId {
+    /// Generates a random identifier.
+    pub fn new() -> Self {
+        let id = hex(&rand::thread_rng().gen::<[u8; 16]>());
+        Self(HeaderValue::try_from(id).unwrap())
+    }
+
+    /// Reads `x-request-id`, ignoring values that are empty, too long or not visible
+    /// ASCII.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let value = headers.get(REQUEST_ID_HEADER)?;
+        let valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LEN
+            && value.as_bytes().iter().all(u8::is_ascii_graphic);
+        valid.then(|| Self(value.clone()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only visible ASCII is accepted
+        self.0.to_str().unwrap()
+    }
+
+    pub fn header_value(&self) -> &HeaderValue {
+        &self.0
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// MARK: TraceContext
+
+/// W3C trace context of a span, as carried by the `traceparent` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    flags: u8,
+}
+
+impl TraceContext {
+    const SAMPLED: u8 = 0x01;
+
+    /// Starts a new sampled trace.
+    pub fn new_root() -> Self {
+        let mut rng = rand::thread_rng();
+        Self {
+            trace_id: rng.gen(),
+            span_id: rng.gen(),
+            flags: Self::SAMPLED,
+        }
+    }
+
+    /// A new span in the same trace, with this span as parent.
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: rand::thread_rng().gen(),
+            ..*self
+        }
+    }
+
+    /// Parses a version `00` `traceparent` value.
+    ///
+    /// Returns `None` for invalid values, including all-zero identifiers, in which
+    /// case the trace should be restarted.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let (version, trace_id, span_id, flags) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if parse_hex::<1>(version)? == [0xff] {
+            return None;
+        }
+        // Later versions may append fields.
+        if version == "00" && parts.next().is_some() {
+            return None;
+        }
+        let trace_id = parse_hex::<16>(trace_id)?;
+        let span_id = parse_hex::<8>(span_id)?;
+        let [flags] = parse_hex::<1>(flags)?;
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(Self {
+            trace_id,
+            span_id,
+            flags,
+        })
+    }
+
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        Self::parse(headers.get(TRACEPARENT_HEADER)?.to_str().ok()?)
+    }
+
+    pub fn trace_id(&self) -> String {
+        hex(&self.trace_id)
+    }
+
+    pub fn span_id(&self) -> String {
+        hex(&self.span_id)
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        self.flags & Self::SAMPLED != 0
+    }
+
+    pub fn header_value(&self) -> HeaderValue {
+        HeaderValue::try_from(self.to_string()).unwrap()
+    }
+
+    /// The context of the OpenTelemetry span backing `span`, if spans are exported.
+    pub fn from_span(span: &tracing::Span) -> Option<Self> {
+        use opentelemetry::trace::TraceContextExt;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let context = span.context();
+        let span_context = context.span().span_context().clone();
+        span_context.is_valid().then(|| Self {
+            trace_id: span_context.trace_id().to_bytes(),
+            span_id: span_context.span_id().to_bytes(),
+            flags: span_context.trace_flags().to_u8(),
+        })
+    }
+
+    /// An OpenTelemetry context with this span as remote parent.
+    pub fn to_otel(&self) -> opentelemetry::Context {
+        use opentelemetry::trace::{
+            SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+        };
+
+        let span_context = SpanContext::new(
+            TraceId::from_bytes(self.trace_id),
+            SpanId::from_bytes(self.span_id),
+            TraceFlags::new(self.flags),
+            true,
+            TraceState::default(),
+        );
+        opentelemetry::Context::new().with_remote_span_context(span_context)
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{}-{}-{:02x}",
+            self.trace_id(),
+            self.span_id(),
+            self.flags
+        )
+    }
+}
+
+/// Trace context received from the caller, parent of the request's [`TraceContext`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParentContext(pub TraceContext);
+
+// MARK: MakeRequestSpan
+
+/// [`tower_http::trace::MakeSpan`] recording the [`RequestId`] and [`TraceContext`]
+/// inserted by [`RequestContextLayer`], along with the ID and peer address of the
+/// [`ConnectionInfo`](crate::serve::ConnectionInfo) if any.
+///
+/// The span is at `INFO` level, so that its fields show up on every log line of
+/// the request with the default filter. When spans are exported, the span continues
+/// the caller's trace and the recorded IDs are those of the exported span. gRPC
+/// requests also get the `rpc.*` attributes of the OpenTelemetry conventions, see
+/// [`crate::metrics::MetricsLayer`] for the ones known once the call ends.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MakeRequestSpan;
+
+impl<B> tower_http::trace::MakeSpan<B> for MakeRequestSpan {
+    fn make_span(&mut self, request: &http::Request<B>) -> tracing::Span {
+        use tracing::field::{display, Empty};
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let extensions = request.extensions();
+        let request_id = extensions.get::<RequestId>().map(display);
+        let connection = extensions.get::<crate::serve::ConnectionInfo>();
+        let parent = extensions.get::<ParentContext>();
+        let path = request.uri().path();
+        let is_grpc = request
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .is_some_and(|ct| ct.as_bytes().starts_with(b"application/grpc"));
+        let name = if is_grpc {
+            path.trim_start_matches('/').to_string()
+        } else {
+            format!("{} {path}", request.method())
+        };
+        let span = tracing::info_span!(
+            "request",
+            method = %request.method(),
+            uri = %request.uri(),
+            version = ?request.version(),
+            request_id,
+            connection_id = connection.map(|c| c.id()),
+            peer_addr = connection.and_then(|c| c.peer_addr()).map(display),
+            trace_id = Empty,
+            span_id = Empty,
+            parent_span_id = parent.map(|p| display(p.0.span_id())),
+            otel.name = name,
+            otel.kind = "server",
+            otel.status_code = Empty,
+            rpc.system = Empty,
+            rpc.service = Empty,
+            rpc.method = Empty,
+            rpc.grpc.status_code = Empty,
+            rpc.message.received_count = Empty,
+            rpc.message.sent_count = Empty,
+        );
+        if let Some(parent) = parent {
+            span.set_parent(parent.0.to_otel());
+        }
+        if is_grpc {
+            let (service, method) = path
+                .trim_start_matches('/')
+                .split_once('/')
+                .unwrap_or_default();
+            span.record("rpc.system", "grpc");
+            span.record("rpc.service", service);
+            span.record("rpc.method", method);
+        }
+        let trace = TraceContext::from_span(&span).or(extensions.get::<TraceContext>().copied());
+        if let Some(trace) = trace {
+            span.record("trace_id", display(trace.trace_id()));
+            span.record("span_id", display(trace.span_id()));
+        }
+        span
+    }
+}
+
+// MARK: Layer
+
+/// Extracts `x-request-id` and `traceparent` into the request extensions, generating
+/// them when missing, and echoes the request ID in the response headers.
+///
+/// The request extensions get a [`RequestId`] and a [`TraceContext`] for the server
+/// span, which is a child of the caller's [`ParentContext`] if any. Place this layer
+/// outside of `TraceLayer`, configured with [`MakeRequestSpan`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestContextLayer;
+
+impl RequestContextLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> tower::Layer<S> for RequestContextLayer {
+    type Service = RequestContextService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestContextService { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestContextService<S> {
+    inner: S,
+}
+
+impl<S, ReqB, ResB> tower::Service<http::Request<ReqB>> for RequestContextService<S>
+where
+    S: tower::Service<http::Request<ReqB>, Response = http::Response<ResB>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<ReqB>) -> Self::Future {
+        let request_id = RequestId::from_headers(req.headers()).unwrap_or_default();
+        let parent = TraceContext::from_headers(req.headers());
+        let trace = parent
+            .as_ref()
+            .map_or_else(TraceContext::new_root, TraceContext::child);
+        let extensions = req.extensions_mut();
+        extensions.insert(request_id.clone());
+        extensions.insert(trace);
+        if let Some(parent) = parent {
+            extensions.insert(ParentContext(parent));
+        }
+        ResponseFuture {
+            inner: self.inner.call(req),
+            request_id: Some(request_id),
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        request_id: Option<RequestId>,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<http::Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut res = ready!(this.inner.poll(cx))?;
+        if let Some(request_id) = this.request_id.take() {
+            res.headers_mut().insert(REQUEST_ID_HEADER, request_id.0);
+        }
+        Poll::Ready(Ok(res))
+    }
+}
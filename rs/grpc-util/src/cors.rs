@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use http::{header, HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Request headers sent by gRPC-Web clients, and the request correlation headers.
+const ALLOW_HEADERS: [HeaderName; 7] = [
+    header::CONTENT_TYPE,
+    header::AUTHORIZATION,
+    crate::context::REQUEST_ID_HEADER,
+    crate::context::TRACEPARENT_HEADER,
+    HeaderName::from_static("x-grpc-web"),
+    HeaderName::from_static("x-user-agent"),
+    HeaderName::from_static("grpc-timeout"),
+];
+
+/// Response headers a browser must be allowed to read to decode gRPC-Web responses,
+/// and the request ID.
+const EXPOSE_HEADERS: [HeaderName; 4] = [
+    HeaderName::from_static("grpc-status"),
+    HeaderName::from_static("grpc-message"),
+    HeaderName::from_static("grpc-status-details-bin"),
+    crate::context::REQUEST_ID_HEADER,
+];
+
+/// Reads `CORS_ALLOWED_ORIGINS`, a comma separated list of origins or `*`.
+///
+/// Returns `None` when unset, in which case no CORS headers are sent and only
+/// same-origin browser requests succeed.
+pub fn layer_from_env() -> anyhow::Result<Option<CorsLayer>> {
+    let Ok(origins) = std::env::var("CORS_ALLOWED_ORIGINS") else {
+        return Ok(None);
+    };
+    let allow_origin = if origins.trim() == "*" {
+        AllowOrigin::any()
+    } else {
+        let origins = origins
+            .split(',')
+            .map(|o| HeaderValue::from_str(o.trim()))
+            .collect::<Result<Vec<_>, _>>()
+            .context("failed to parse CORS_ALLOWED_ORIGINS value")?;
+        AllowOrigin::list(origins)
+    };
+    let layer = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers(ALLOW_HEADERS)
+        .expose_headers(EXPOSE_HEADERS)
+        .max_age(DEFAULT_MAX_AGE);
+    Ok(Some(layer))
+}
@@ -0,0 +1,93 @@
+use std::{fmt, time::Duration};
+
+use tonic::Status;
+pub use tonic_types::{ErrorDetails, FieldViolation, StatusExt};
+
+/// `ErrorInfo` domain of the errors of the layers of this crate.
+pub const DOMAIN: &str = "grpc-util";
+
+/// Reasons of the `ErrorInfo` of this crate's errors.
+pub mod reasons {
+    /// No `authorization` header on a call that requires one.
+    pub const MISSING_CREDENTIALS: &str = "MISSING_CREDENTIALS";
+    /// An `authorization` header that isn't a bearer token.
+    pub const MALFORMED_CREDENTIALS: &str = "MALFORMED_CREDENTIALS";
+    /// An unknown API key, or an invalid or expired JWT.
+    pub const INVALID_CREDENTIALS: &str = "INVALID_CREDENTIALS";
+    /// The caller's roles don't grant the method, see `metadata.method`.
+    pub const METHOD_NOT_ALLOWED: &str = "METHOD_NOT_ALLOWED";
+    /// The caller's token bucket is empty, retry after the `RetryInfo` delay.
+    pub const RATE_LIMITED: &str = "RATE_LIMITED";
+    /// Too many calls in progress, for the caller or in total.
+    pub const TOO_MANY_STREAMS: &str = "TOO_MANY_STREAMS";
+    /// The server is shutting down, retry on another one.
+    pub const SHUTTING_DOWN: &str = "SHUTTING_DOWN";
+}
+
+/// `ErrorInfo` reason of `status`, if any.
+pub fn reason(status: &Status) -> Option<String> {
+    status.get_details_error_info().map(|info| info.reason)
+}
+
+/// How long to wait before retrying, as told by the `RetryInfo` of `status`.
+pub fn retry_delay(status: &Status) -> Option<Duration> {
+    status.get_details_retry_info()?.retry_delay
+}
+
+// MARK: Details
+
+/// Displays the `google.rpc` error details of a status, for logs: reason, retry
+/// delay, resource and field violations. Displays nothing when there are none.
+///
+/// ```ignore
+/// tracing::error!(code = %status.code(), message = status.message(), details = %Details::of(&status));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Details(ErrorDetails);
+
+impl Details {
+    pub fn of(status: &Status) -> Self {
+        Self(status.get_error_details())
+    }
+}
+
+impl fmt::Display for Details {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sep = "";
+        let mut item = |f: &mut fmt::Formatter<'_>| {
+            let res = f.write_str(sep);
+            sep = "; ";
+            res
+        };
+        let details = &self.0;
+        if let Some(info) = details.error_info() {
+            item(f)?;
+            write!(f, "reason {} ({})", info.reason, info.domain)?;
+            let mut metadata: Vec<_> = info.metadata.iter().collect();
+            metadata.sort();
+            for (k, v) in metadata {
+                write!(f, " {k}={v}")?;
+            }
+        }
+        if let Some(delay) = details.retry_info().and_then(|r| r.retry_delay) {
+            item(f)?;
+            write!(f, "retry after {delay:?}")?;
+        }
+        if let Some(resource) = details.resource_info() {
+            item(f)?;
+            write!(f, "{} {}", resource.resource_type, resource.resource_name)?;
+            if !resource.description.is_empty() {
+                write!(f, ": {}", resource.description)?;
+            }
+        }
+        for violation in details
+            .bad_request()
+            .iter()
+            .flat_map(|b| &b.field_violations)
+        {
+            item(f)?;
+            write!(f, "{}: {}", violation.field, violation.description)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+use tonic_health::{server::HealthReporter, ServingStatus};
+
+use crate::shutdown::Shutdown;
+
+/// Marks `services` and the server as a whole as `NOT_SERVING` once `shutdown` is
+/// triggered.
+pub fn not_serving_on_shutdown<I>(reporter: &HealthReporter, shutdown: &Shutdown, services: I)
+where
+    I: IntoIterator<Item = &'static str>,
+{
+    let mut reporter = reporter.clone();
+    let shutdown = shutdown.clone();
+    let services: Vec<_> = services.into_iter().chain([""]).collect();
+    tokio::spawn(async move {
+        shutdown.triggered().await;
+        for service in services {
+            reporter
+                .set_service_status(service, ServingStatus::NotServing)
+                .await;
+        }
+        tracing::debug!("Reported NOT_SERVING for shutdown");
+    });
+}
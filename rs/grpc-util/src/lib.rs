@@ -0,0 +1,36 @@
+pub mod auth;
+pub mod bootstrap;
+pub mod client;
+pub mod compression;
+pub mod context;
+pub mod cors;
+pub mod errors;
+pub mod health;
+pub mod limit;
+pub mod logging;
+pub mod metrics;
+pub mod routes;
+pub mod serve;
+pub mod shutdown;
+pub mod telemetry;
+pub mod tls;
+pub mod transport;
+
+/// Parses the `key` environment variable, `None` when unset.
+pub(crate) fn env_parse<T>(key: &str) -> anyhow::Result<Option<T>>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    use anyhow::Context;
+
+    match std::env::var(key) {
+        Ok(v) => {
+            let v = v
+                .parse()
+                .with_context(|| format!("failed to parse {key} value"))?;
+            Ok(Some(v))
+        }
+        Err(_) => Ok(None),
+    }
+}
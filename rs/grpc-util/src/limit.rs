@@ -0,0 +1,451 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::extract::ConnectInfo;
+use futures::future::Either;
+use http_body::Frame;
+use tonic::Status;
+
+use crate::{
+    auth::Caller,
+    env_parse,
+    errors::{reasons, ErrorDetails, StatusExt},
+    serve::ConnectionInfo,
+};
+
+/// Retry hint sent when a stream limit is reached, since there is no telling when a
+/// stream will end.
+const STREAM_LIMIT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Number of clients above which idle rate limit buckets are dropped.
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+// MARK: ClientKey
+
+/// Client that limits are accounted to: the authenticated [`Caller`] if any, else the
+/// peer IP address. Unix domain socket and in-memory clients share one key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ClientKey {
+    Caller(String),
+    Addr(IpAddr),
+    Local,
+    Unknown,
+}
+
+impl ClientKey {
+    fn of(extensions: &http::Extensions) -> Self {
+        if let Some(caller) = extensions.get::<Caller>() {
+            return Self::Caller(caller.subject().to_string());
+        }
+        if let Some(info) = extensions.get::<ConnectionInfo>() {
+            return match info.peer_addr() {
+                Some(addr) => Self::Addr(addr.ip()),
+                None => Self::Local,
+            };
+        }
+        // served by something else than `crate::serve::Server`, e.g. `axum::serve`
+        match extensions.get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => Self::Addr(addr.ip()),
+            None => Self::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for ClientKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Caller(subject) => f.write_str(subject),
+            Self::Addr(addr) => addr.fmt(f),
+            Self::Local => f.write_str("<local>"),
+            Self::Unknown => f.write_str("<unknown>"),
+        }
+    }
+}
+
+// MARK: RateLimit
+
+/// Token bucket refilled at `per_second`, holding up to `burst` calls.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    per_second: f64,
+    burst: f64,
+}
+
+impl RateLimit {
+    pub fn new(per_second: f64, burst: u32) -> Self {
+        Self {
+            per_second,
+            burst: burst.max(1) as f64,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(limit.burst);
+        self.updated = now;
+    }
+
+    /// Takes a token, or returns how long until one is available.
+    fn take(&mut self, limit: &RateLimit) -> Result<(), Duration> {
+        self.refill(limit, Instant::now());
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        let wait = (1.0 - self.tokens) / limit.per_second;
+        Err(Duration::from_secs_f64(wait))
+    }
+}
+
+// MARK: LimitLayer
+
+#[derive(Debug, Default)]
+struct State {
+    buckets: HashMap<ClientKey, Bucket>,
+    /// When idle buckets were last dropped.
+    pruned: Option<Instant>,
+    streams: HashMap<ClientKey, usize>,
+    total_streams: usize,
+}
+
+/// Rejects calls above a per-client rate or stream count, or above a global stream
+/// count, with `RESOURCE_EXHAUSTED`.
+///
+/// A stream is counted from the request until the end of its response body, or of
+/// the connection when upgraded, e.g. to a WebSocket, if served by
+/// [`Server`](crate::serve::Server). Rejected gRPC calls carry a
+/// `grpc-retry-pushback-ms` hint along with `ErrorInfo` and `RetryInfo` details, and
+/// other requests are answered with `429 Too Many Requests` and `retry-after`.
+#[derive(Debug, Clone, Default)]
+pub struct LimitLayer {
+    rate: Option<RateLimit>,
+    max_streams_per_client: Option<usize>,
+    max_streams: Option<usize>,
+    state: Arc<Mutex<State>>,
+}
+
+impl LimitLayer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn rate(self, rate: RateLimit) -> Self {
+        Self {
+            rate: Some(rate),
+            ..self
+        }
+    }
+
+    pub fn max_streams_per_client(self, max: usize) -> Self {
+        Self {
+            max_streams_per_client: Some(max),
+            ..self
+        }
+    }
+
+    pub fn max_streams(self, max: usize) -> Self {
+        Self {
+            max_streams: Some(max),
+            ..self
+        }
+    }
+
+    /// Reads `RATE_LIMIT_PER_SECOND`, `RATE_LIMIT_BURST`, `MAX_STREAMS_PER_CLIENT` and
+    /// `MAX_STREAMS`.
+    ///
+    /// The burst defaults to one second worth of calls. Limits that are unset are
+    /// not enforced.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let per_second: Option<f64> = env_parse("RATE_LIMIT_PER_SECOND")?;
+        let burst: Option<u32> = env_parse("RATE_LIMIT_BURST")?;
+        let max_streams_per_client = env_parse("MAX_STREAMS_PER_CLIENT")?;
+        let max_streams = env_parse("MAX_STREAMS")?;
+        let mut layer = Self::new();
+        if let Some(per_second) = per_second {
+            anyhow::ensure!(per_second > 0.0, "RATE_LIMIT_PER_SECOND must be positive");
+            let burst = burst.unwrap_or(per_second.ceil() as u32);
+            layer = layer.rate(RateLimit::new(per_second, burst));
+        }
+        if let Some(max) = max_streams_per_client {
+            layer = layer.max_streams_per_client(max);
+        }
+        if let Some(max) = max_streams {
+            layer = layer.max_streams(max);
+        }
+        if layer.is_limited() {
+            tracing::info!(
+                rate = ?layer.rate,
+                max_streams_per_client = layer.max_streams_per_client,
+                max_streams = layer.max_streams,
+                "Limits enabled"
+            );
+        }
+        Ok(layer)
+    }
+
+    fn is_limited(&self) -> bool {
+        self.rate.is_some() || self.max_streams_per_client.is_some() || self.max_streams.is_some()
+    }
+
+    /// Admits a call from `client`, or returns the reason and a retry hint.
+    fn acquire(&self, client: ClientKey) -> Result<StreamPermit, (Status, Duration)> {
+        let mut state = self.state.lock().unwrap();
+        if self
+            .max_streams
+            .is_some_and(|max| state.total_streams >= max)
+        {
+            let status = limited(
+                "Too many concurrent streams".to_string(),
+                reasons::TOO_MANY_STREAMS,
+                &client,
+                STREAM_LIMIT_RETRY_AFTER,
+            );
+            return Err((status, STREAM_LIMIT_RETRY_AFTER));
+        }
+        let streams = state.streams.get(&client).copied().unwrap_or_default();
+        if self
+            .max_streams_per_client
+            .is_some_and(|max| streams >= max)
+        {
+            let message = format!("Too many concurrent streams for {client}");
+            let status = limited(
+                message,
+                reasons::TOO_MANY_STREAMS,
+                &client,
+                STREAM_LIMIT_RETRY_AFTER,
+            );
+            return Err((status, STREAM_LIMIT_RETRY_AFTER));
+        }
+        // Only calls admitted otherwise take a token.
+        if let Some(rate) = &self.rate {
+            let now = Instant::now();
+            // Rescanning only pays off once buckets had time to refill.
+            let refill = Duration::from_secs_f64(rate.burst / rate.per_second);
+            if state.buckets.len() > MAX_IDLE_BUCKETS
+                && state.pruned.is_none_or(|t| now.duration_since(t) >= refill)
+            {
+                // Full buckets are indistinguishable from new ones.
+                state.buckets.retain(|_, b| {
+                    b.refill(rate, now);
+                    b.tokens < rate.burst
+                });
+                state.pruned = Some(now);
+            }
+            let bucket = state.buckets.entry(client.clone()).or_insert(Bucket {
+                tokens: rate.burst,
+                updated: now,
+            });
+            if let Err(wait) = bucket.take(rate) {
+                let message = format!("Rate limit exceeded for {client}");
+                let status = limited(message, reasons::RATE_LIMITED, &client, wait);
+                return Err((status, wait));
+            }
+        }
+        state.total_streams += 1;
+        *state.streams.entry(client.clone()).or_default() += 1;
+        Ok(StreamPermit {
+            state: self.state.clone(),
+            client,
+        })
+    }
+}
+
+/// `RESOURCE_EXHAUSTED` status with an `ErrorInfo` naming the client, and a
+/// `RetryInfo` of `retry_after`.
+fn limited(message: String, reason: &str, client: &ClientKey, retry_after: Duration) -> Status {
+    let mut details = ErrorDetails::with_retry_info(Some(retry_after));
+    let metadata = HashMap::from([("client".to_string(), client.to_string())]);
+    details.set_error_info(reason, crate::errors::DOMAIN, metadata);
+    Status::with_error_details(tonic::Code::ResourceExhausted, message, details)
+}
+
+/// Counts a stream until dropped.
+struct StreamPermit {
+    state: Arc<Mutex<State>>,
+    client: ClientKey,
+}
+
+impl Drop for StreamPermit {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.total_streams -= 1;
+        if let Some(streams) = state.streams.get_mut(&self.client) {
+            *streams -= 1;
+            if *streams == 0 {
+                state.streams.remove(&self.client);
+            }
+        }
+    }
+}
+
+impl<S> tower::Layer<S> for LimitLayer {
+    type Service = LimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LimitService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LimitService<S> {
+    inner: S,
+    layer: LimitLayer,
+}
+
+fn rejection<B: Default>(
+    is_grpc: bool,
+    status: Status,
+    retry_after: Duration,
+) -> http::Response<B> {
+    if is_grpc {
+        let (mut parts, _) = status.into_http().into_parts();
+        let pushback = http::HeaderValue::from(retry_after.as_millis() as u64);
+        parts.headers.insert("grpc-retry-pushback-ms", pushback);
+        return http::Response::from_parts(parts, B::default());
+    }
+    // Rounded up, since `retry-after` is in whole seconds.
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let mut res = http::Response::new(B::default());
+    *res.status_mut() = http::StatusCode::TOO_MANY_REQUESTS;
+    res.headers_mut()
+        .insert(http::header::RETRY_AFTER, http::HeaderValue::from(seconds));
+    res
+}
+
+impl<S, ReqB, ResB> tower::Service<http::Request<ReqB>> for LimitService<S>
+where
+    S: tower::Service<http::Request<ReqB>, Response = http::Response<ResB>>,
+    ResB: Default,
+{
+    type Response = http::Response<LimitBody<ResB>>;
+    type Error = S::Error;
+    type Future =
+        Either<ResponseFuture<S::Future>, std::future::Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<ReqB>) -> Self::Future {
+        if !self.layer.is_limited() {
+            return Either::Left(ResponseFuture {
+                inner: self.inner.call(req),
+                permit: None,
+                connection: None,
+            });
+        }
+        let client = ClientKey::of(req.extensions());
+        match self.layer.acquire(client) {
+            Ok(permit) => Either::Left(ResponseFuture {
+                connection: req.extensions().get::<ConnectionInfo>().cloned(),
+                inner: self.inner.call(req),
+                permit: Some(permit),
+            }),
+            Err((status, retry_after)) => {
+                tracing::debug!(message = status.message(), ?retry_after, "Rejected call");
+                let is_grpc = req
+                    .headers()
+                    .get(http::header::CONTENT_TYPE)
+                    .is_some_and(|ct| ct.as_bytes().starts_with(b"application/grpc"));
+                let res = rejection::<ResB>(is_grpc, status, retry_after).map(|inner| LimitBody {
+                    inner,
+                    permit: None,
+                });
+                Either::Right(std::future::ready(Ok(res)))
+            }
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        permit: Option<StreamPermit>,
+        connection: Option<ConnectionInfo>,
+    }
+}
+
+impl<F, B, E> std::future::Future for ResponseFuture<F>
+where
+    F: std::future::Future<Output = Result<http::Response<B>, E>>,
+{
+    type Output = Result<http::Response<LimitBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = std::task::ready!(this.inner.poll(cx))?;
+        let mut permit = this.permit.take();
+        // The body of an upgrade ends right away, unlike the upgraded connection.
+        if res.status() == http::StatusCode::SWITCHING_PROTOCOLS {
+            if let (Some(permit), Some(connection)) = (permit.take(), this.connection.take()) {
+                connection.hold_until_closed(permit);
+            }
+        }
+        Poll::Ready(Ok(res.map(|inner| LimitBody { inner, permit })))
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// Response body holding the stream permit until it ends.
+    pub struct LimitBody<B> {
+        #[pin]
+        inner: B,
+        permit: Option<StreamPermit>,
+    }
+}
+
+impl<B: Default> Default for LimitBody<B> {
+    fn default() -> Self {
+        Self {
+            inner: B::default(),
+            permit: None,
+        }
+    }
+}
+
+impl<B> http_body::Body for LimitBody<B>
+where
+    B: http_body::Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = std::task::ready!(this.inner.poll_frame(cx));
+        if frame.is_none() {
+            this.permit.take();
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+
+use anyhow::Context as _;
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing_subscriber::{
+    fmt::{
+        format::{JsonFields, Writer},
+        time::{FormatTime, SystemTime},
+        FmtContext, FormatEvent, FormattedFields,
+    },
+    registry::LookupSpan,
+};
+
+/// Output format of the log lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One [`JsonFormat`] object per line.
+    Json,
+}
+
+impl LogFormat {
+    /// Reads `LOG_FORMAT`, `text` (default) or `json`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("LOG_FORMAT") {
+            Ok(v) => v.parse().context("failed to parse LOG_FORMAT value"),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" | "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            s => anyhow::bail!("Unknown log format {s}"),
+        }
+    }
+}
+
+/// Formats events as flat JSON objects, for log pipelines.
+///
+/// Each line has the `timestamp`, `level`, `target`, `span` (name of the innermost
+/// span, `null` outside of spans) and `message` keys, along with the fields of the
+/// enclosing spans and of the event, inner ones taking precedence. Within a
+/// request, these include the `request_id`, `trace_id`, `rpc.method` and, once
+/// the call ended, `rpc.grpc.status_code` fields of
+/// [`MakeRequestSpan`](crate::context::MakeRequestSpan).
+///
+/// Span fields must be formatted with [`JsonFields`]. The `otel.*` fields, which only
+/// configure exported spans, are left out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFormat;
+
+impl<S> FormatEvent<S, JsonFields> for JsonFormat
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        let metadata = event.metadata();
+        let mut object = Map::new();
+        let mut name = None;
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                name = Some(span.name());
+                let extensions = span.extensions();
+                let Some(fields) = extensions.get::<FormattedFields<JsonFields>>() else {
+                    continue;
+                };
+                if let Ok(Value::Object(fields)) = serde_json::from_str(fields) {
+                    object.extend(fields);
+                }
+            }
+        }
+        object.insert("span".into(), name.into());
+        event.record(&mut JsonVisitor(&mut object));
+        object.retain(|key, _| !key.starts_with("otel."));
+        object.insert("timestamp".into(), timestamp.into());
+        object.insert("level".into(), metadata.level().as_str().into());
+        object.insert("target".into(), metadata.target().into());
+        let line = serde_json::to_string(&object).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{line}")
+    }
+}
+
+/// Collects event fields into a JSON object.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl JsonVisitor<'_> {
+    fn insert(&mut self, field: &Field, value: impl Into<Value>) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+}
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value);
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value);
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value);
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.insert(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{value:?}"));
+    }
+}
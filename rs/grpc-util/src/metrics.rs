@@ -0,0 +1,557 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock,
+    },
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, Bytes};
+use http_body::Frame;
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter_vec, register_int_gauge_vec,
+    HistogramVec, IntCounterVec, IntGaugeVec,
+};
+use tonic::Code;
+
+use crate::{serve::Server, shutdown::Shutdown};
+
+// MARK: collectors
+
+static STARTED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "grpc_server_started_total",
+        "Total number of RPCs started on the server.",
+        &["grpc_service", "grpc_method"]
+    )
+    .unwrap()
+});
+
+static HANDLED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "grpc_server_handled_total",
+        "Total number of RPCs completed on the server, regardless of success or failure.",
+        &["grpc_service", "grpc_method", "grpc_code"]
+    )
+    .unwrap()
+});
+
+static HANDLING_SECONDS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "grpc_server_handling_seconds",
+        "Time from receiving an RPC until its response stream is complete.",
+        &["grpc_service", "grpc_method"]
+    )
+    .unwrap()
+});
+
+static IN_FLIGHT: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "grpc_server_streams_in_flight",
+        "Number of RPCs, unary or streaming, currently being handled.",
+        &["grpc_service", "grpc_method"]
+    )
+    .unwrap()
+});
+
+static MSG_RECEIVED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "grpc_server_msg_received_total",
+        "Total number of messages received from clients.",
+        &["grpc_service", "grpc_method"]
+    )
+    .unwrap()
+});
+
+static MSG_SENT: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "grpc_server_msg_sent_total",
+        "Total number of messages sent to clients.",
+        &["grpc_service", "grpc_method"]
+    )
+    .unwrap()
+});
+
+static MSGS_PER_STREAM: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "grpc_server_msgs_per_stream",
+        "Number of messages in each direction of an RPC.",
+        &["grpc_service", "grpc_method", "direction"],
+        exponential_buckets(1.0, 4.0, 8).unwrap()
+    )
+    .unwrap()
+});
+
+/// Label used by other gRPC implementations for `code`.
+fn code_label(code: Code) -> &'static str {
+    match code {
+        Code::Ok => "OK",
+        Code::Cancelled => "Canceled",
+        Code::Unknown => "Unknown",
+        Code::InvalidArgument => "InvalidArgument",
+        Code::DeadlineExceeded => "DeadlineExceeded",
+        Code::NotFound => "NotFound",
+        Code::AlreadyExists => "AlreadyExists",
+        Code::PermissionDenied => "PermissionDenied",
+        Code::ResourceExhausted => "ResourceExhausted",
+        Code::FailedPrecondition => "FailedPrecondition",
+        Code::Aborted => "Aborted",
+        Code::OutOfRange => "OutOfRange",
+        Code::Unimplemented => "Unimplemented",
+        Code::Internal => "Internal",
+        Code::Unavailable => "Unavailable",
+        Code::DataLoss => "DataLoss",
+        Code::Unauthenticated => "Unauthenticated",
+    }
+}
+
+fn grpc_status(headers: &http::HeaderMap) -> Option<Code> {
+    let status = headers.get("grpc-status")?;
+    Some(Code::from_bytes(status.as_bytes()))
+}
+
+// MARK: exporter
+
+/// Renders every registered metric in the Prometheus text format.
+pub fn render() -> String {
+    use prometheus::Encoder;
+
+    let mut buf = Vec::new();
+    let encoder = prometheus::TextEncoder::new();
+    encoder
+        .encode(&prometheus::gather(), &mut buf)
+        .inspect_err(|e| tracing::error!(error = e as &dyn std::error::Error, "Encode failed"))
+        .ok();
+    String::from_utf8(buf).unwrap_or_default()
+}
+
+/// Method authorized for `/metrics` behind an [`AuthLayer`](crate::auth::AuthLayer),
+/// which `admin` callers may call with the default policies.
+pub const METRICS_METHOD: &str = "grpc_util.Admin/Metrics";
+
+/// `GET /metrics`, for callers allowed to call [`METRICS_METHOD`].
+pub fn router() -> axum::Router {
+    use axum::response::IntoResponse;
+
+    let handler = |extensions: http::Extensions| async move {
+        if let Err(status) = crate::auth::authorize(&extensions, METRICS_METHOD) {
+            let code = match status.code() {
+                Code::Unauthenticated => http::StatusCode::UNAUTHORIZED,
+                _ => http::StatusCode::FORBIDDEN,
+            };
+            return (code, status.message().to_string()).into_response();
+        }
+        let content_type = [(http::header::CONTENT_TYPE, prometheus::TEXT_FORMAT)];
+        (content_type, render()).into_response()
+    };
+    axum::Router::new().route("/metrics", axum::routing::get(handler))
+}
+
+/// Reads `METRICS_PORT`, the port of an optional listener dedicated to `/metrics`.
+pub fn port_from_env() -> anyhow::Result<Option<u16>> {
+    use anyhow::Context as _;
+
+    let Ok(port) = std::env::var("METRICS_PORT") else {
+        return Ok(None);
+    };
+    let port = port.parse().context("failed to parse METRICS_PORT value")?;
+    Ok(Some(port))
+}
+
+/// Serves [`router`] on `0.0.0.0:{port}` in the background until `shutdown`.
+pub async fn spawn_listener(port: u16, shutdown: Shutdown) -> anyhow::Result<()> {
+    let addr: SocketAddr = ([0, 0, 0, 0], port).into();
+    let server = Server::bind(addr)
+        .await?
+        .graceful_shutdown(shutdown, Duration::ZERO);
+    tracing::info!(%addr, "metrics listening");
+    tokio::spawn(async move {
+        if let Err(e) = server.serve(router()).await {
+            tracing::error!(
+                error = &*e as &dyn std::error::Error,
+                "Metrics listener failed"
+            );
+        }
+    });
+    Ok(())
+}
+
+// MARK: GrpcMethod
+
+#[derive(Debug, Clone)]
+struct GrpcMethod {
+    service: Arc<str>,
+    method: Arc<str>,
+}
+
+impl GrpcMethod {
+    fn unknown() -> Self {
+        Self {
+            service: "unknown".into(),
+            method: "unknown".into(),
+        }
+    }
+
+    fn labels(&self) -> [&str; 2] {
+        [&self.service, &self.method]
+    }
+}
+
+/// Methods of the services described by `file_descriptor_sets`, by path.
+fn known_methods(file_descriptor_sets: &[&[u8]]) -> anyhow::Result<HashMap<String, GrpcMethod>> {
+    use anyhow::Context as _;
+    use prost::Message as _;
+
+    let mut methods = HashMap::new();
+    for fds in file_descriptor_sets {
+        let fds = prost_types::FileDescriptorSet::decode(*fds)
+            .context("Failed to decode file descriptor set")?;
+        for file in fds.file {
+            for service in &file.service {
+                let name = match file.package() {
+                    "" => service.name().to_string(),
+                    package => format!("{package}.{}", service.name()),
+                };
+                let name: Arc<str> = name.into();
+                for method in &service.method {
+                    let path = format!("/{name}/{}", method.name());
+                    let method = GrpcMethod {
+                        service: name.clone(),
+                        method: method.name().into(),
+                    };
+                    methods.insert(path, method);
+                }
+            }
+        }
+    }
+    Ok(methods)
+}
+
+// MARK: Layer
+
+/// Records per-method request counts by status code, latencies, in-flight calls and
+/// message counts of the gRPC requests passing through.
+///
+/// The status code and message counts are also recorded on the current span, as
+/// the `rpc.grpc.status_code`, `rpc.message.received_count` and
+/// `rpc.message.sent_count` fields declared by
+/// [`MakeRequestSpan`](crate::context::MakeRequestSpan), and a `Finished call`
+/// event is logged in that span with the status and duration.
+///
+/// Calls are labelled by service and method only when their path names a method of
+/// [`Self::with_file_descriptor_sets`], and as `unknown` otherwise, so that clients
+/// can't create series at will.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsLayer {
+    methods: Arc<HashMap<String, GrpcMethod>>,
+}
+
+impl MetricsLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Labels calls to the methods of the services described by
+    /// `file_descriptor_sets` by their name.
+    pub fn with_file_descriptor_sets(self, file_descriptor_sets: &[&[u8]]) -> anyhow::Result<Self> {
+        let mut methods = Arc::unwrap_or_clone(self.methods);
+        methods.extend(known_methods(file_descriptor_sets)?);
+        Ok(Self {
+            methods: Arc::new(methods),
+        })
+    }
+}
+
+impl<S> tower::Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            methods: self.methods.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+    methods: Arc<HashMap<String, GrpcMethod>>,
+}
+
+impl<S, ReqB, ResB> tower::Service<http::Request<ReqB>> for MetricsService<S>
+where
+    S: tower::Service<http::Request<axum::body::Body>, Response = http::Response<ResB>>,
+    ReqB: http_body::Body<Data = Bytes> + Send + 'static,
+    ReqB::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    ResB: http_body::Body<Data = Bytes>,
+{
+    type Response = http::Response<MetricsBody<ResB>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<ReqB>) -> Self::Future {
+        let method = self.methods.get(req.uri().path()).cloned();
+        let call = CallGuard::start(method.unwrap_or_else(GrpcMethod::unknown));
+        let received = call.received.clone();
+        let req = req.map(|body| {
+            axum::body::Body::new(RequestBody {
+                inner: body,
+                counter: FrameCounter::default(),
+                received,
+            })
+        });
+        ResponseFuture {
+            inner: self.inner.call(req),
+            call: Some(call),
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        call: Option<CallGuard>,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<http::Response<B>, E>>,
+{
+    type Output = Result<http::Response<MetricsBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx));
+        let mut call = this.call.take().expect("polled after completion");
+        let res = match res {
+            Ok(res) => res,
+            Err(e) => {
+                call.code = Some(Code::Unknown);
+                return Poll::Ready(Err(e));
+            }
+        };
+        // A trailers-only response carries the status in its headers.
+        let call = match grpc_status(res.headers()) {
+            Some(code) => {
+                call.code = Some(code);
+                None
+            }
+            None => Some(call),
+        };
+        let res = res.map(|inner| MetricsBody {
+            inner,
+            counter: FrameCounter::default(),
+            call,
+        });
+        Poll::Ready(Ok(res))
+    }
+}
+
+// MARK: bodies
+
+pin_project_lite::pin_project! {
+    struct RequestBody<B> {
+        #[pin]
+        inner: B,
+        counter: FrameCounter,
+        received: Arc<AtomicU64>,
+    }
+}
+
+impl<B> http_body::Body for RequestBody<B>
+where
+    B: http_body::Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = ready!(this.inner.poll_frame(cx));
+        if let Some(data) = frame.as_ref().and_then(|f| f.as_ref().ok()?.data_ref()) {
+            let messages = this.counter.feed(data.chunk());
+            this.received.fetch_add(messages, Ordering::Relaxed);
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+pin_project_lite::pin_project! {
+    pub struct MetricsBody<B> {
+        #[pin]
+        inner: B,
+        counter: FrameCounter,
+        call: Option<CallGuard>,
+    }
+}
+
+impl<B> http_body::Body for MetricsBody<B>
+where
+    B: http_body::Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = ready!(this.inner.poll_frame(cx));
+        let Some(call) = this.call.as_mut() else {
+            return Poll::Ready(frame);
+        };
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    call.sent += this.counter.feed(data.chunk());
+                } else if let Some(trailers) = frame.trailers_ref() {
+                    call.code = Some(grpc_status(trailers).unwrap_or(Code::Unknown));
+                    this.call.take();
+                }
+            }
+            Some(Err(_)) => {
+                call.code = Some(Code::Internal);
+                this.call.take();
+            }
+            None => {
+                call.code = Some(Code::Unknown);
+                this.call.take();
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Counts the length-prefixed gRPC messages in a byte stream.
+#[derive(Debug, Default)]
+struct FrameCounter {
+    header: [u8; 5],
+    header_len: usize,
+    remaining: usize,
+}
+
+impl FrameCounter {
+    /// Returns the number of messages started in `buf`.
+    fn feed(&mut self, mut buf: &[u8]) -> u64 {
+        let mut messages = 0;
+        while !buf.is_empty() {
+            if self.remaining > 0 {
+                let n = self.remaining.min(buf.len());
+                self.remaining -= n;
+                buf = &buf[n..];
+                continue;
+            }
+            let n = (5 - self.header_len).min(buf.len());
+            self.header[self.header_len..self.header_len + n].copy_from_slice(&buf[..n]);
+            self.header_len += n;
+            buf = &buf[n..];
+            if self.header_len == 5 {
+                let len = u32::from_be_bytes(self.header[1..].try_into().unwrap());
+                self.remaining = len as usize;
+                self.header_len = 0;
+                messages += 1;
+            }
+        }
+        messages
+    }
+}
+
+// MARK: CallGuard
+
+/// Tracks one RPC, recording its outcome when dropped.
+///
+/// The call is considered cancelled if it is dropped before a status is known.
+struct CallGuard {
+    method: GrpcMethod,
+    span: tracing::Span,
+    start: Instant,
+    received: Arc<AtomicU64>,
+    sent: u64,
+    code: Option<Code>,
+}
+
+impl CallGuard {
+    fn start(method: GrpcMethod) -> Self {
+        let labels = method.labels();
+        STARTED.with_label_values(&labels).inc();
+        IN_FLIGHT.with_label_values(&labels).inc();
+        Self {
+            method,
+            span: tracing::Span::current(),
+            start: Instant::now(),
+            received: Default::default(),
+            sent: 0,
+            code: None,
+        }
+    }
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        let [service, method] = self.method.labels();
+        let labels = [service, method];
+        let code = self.code.unwrap_or(Code::Cancelled);
+        let received = self.received.load(Ordering::Relaxed);
+        self.span.record("rpc.grpc.status_code", code as i32);
+        // OpenTelemetry attributes are signed, unsigned values are exported as strings
+        self.span
+            .record("rpc.message.received_count", received as i64);
+        self.span.record("rpc.message.sent_count", self.sent as i64);
+        if code != Code::Ok {
+            self.span.record("otel.status_code", "ERROR");
+        }
+        let code = code_label(code);
+        let elapsed = self.start.elapsed();
+        tracing::info!(
+            parent: &self.span,
+            grpc.status = code,
+            duration_ms = elapsed.as_secs_f64() * 1000.0,
+            "Finished call"
+        );
+        IN_FLIGHT.with_label_values(&labels).dec();
+        HANDLED.with_label_values(&[service, method, code]).inc();
+        HANDLING_SECONDS
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+        MSG_RECEIVED.with_label_values(&labels).inc_by(received);
+        MSG_SENT.with_label_values(&labels).inc_by(self.sent);
+        MSGS_PER_STREAM
+            .with_label_values(&[service, method, "received"])
+            .observe(received as f64);
+        MSGS_PER_STREAM
+            .with_label_values(&[service, method, "sent"])
+            .observe(self.sent as f64);
+    }
+}
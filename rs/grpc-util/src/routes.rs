@@ -0,0 +1,113 @@
+use std::{collections::HashSet, convert::Infallible, sync::Arc};
+
+use anyhow::Context;
+use axum::response::IntoResponse;
+use tonic::server::NamedService;
+
+/// Dispatches gRPC requests to services by their `/{package.Service}/` path prefix.
+///
+/// Requests for services that were not added are answered with `UNIMPLEMENTED`.
+#[derive(Debug, Clone)]
+pub struct GrpcRouter {
+    router: axum::Router,
+    services: Arc<HashSet<&'static str>>,
+}
+
+impl Default for GrpcRouter {
+    fn default() -> Self {
+        let router = axum::Router::new()
+            .fallback(|| async { tonic::Status::unimplemented("Unknown service").into_http() });
+        Self {
+            router,
+            services: Default::default(),
+        }
+    }
+}
+
+impl GrpcRouter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_service<S>(self, service: S) -> Self
+    where
+        S: NamedService
+            + tower::Service<http::Request<axum::body::Body>, Error = Infallible>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        S::Response: IntoResponse,
+        S::Future: Send,
+    {
+        let path = format!("/{}/{{*method}}", S::NAME);
+        let router = self.router.route_service(&path, service);
+        let mut services = Arc::unwrap_or_clone(self.services);
+        services.insert(S::NAME);
+        Self {
+            router,
+            services: Arc::new(services),
+        }
+    }
+
+    /// Adds `grpc.reflection.v1` and `grpc.reflection.v1alpha` describing the services
+    /// in `file_descriptor_sets`, along with `grpc.health.v1.Health`.
+    pub fn add_reflection(self, file_descriptor_sets: &[&[u8]]) -> anyhow::Result<Self> {
+        let builder = || {
+            file_descriptor_sets.iter().fold(
+                tonic_reflection::server::Builder::configure()
+                    .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET),
+                |builder, fds| builder.register_encoded_file_descriptor_set(fds),
+            )
+        };
+        let v1 = builder()
+            .build_v1()
+            .context("Failed to build reflection service")?;
+        let v1alpha = builder()
+            .build_v1alpha()
+            .context("Failed to build reflection service")?;
+        Ok(self.add_service(v1).add_service(v1alpha))
+    }
+
+    /// Whether `req` is to be dispatched to this router when sharing a port with
+    /// plain HTTP routes.
+    ///
+    /// That is the case of [gRPC requests](is_grpc_request), which are answered with
+    /// `UNIMPLEMENTED` for services that were not added, and of any request for a
+    /// `/{package.Service}/{Method}` of an added service. Those are checked by
+    /// [`AuthLayer`](crate::auth::AuthLayer) as calls to that method, even when they
+    /// aren't gRPC. Other paths are left to the HTTP routes.
+    pub fn handles<B>(&self, req: &http::Request<B>) -> bool {
+        is_grpc_request(req) || self.handles_path(req.uri().path())
+    }
+
+    fn handles_path(&self, path: &str) -> bool {
+        let Some((service, method)) = path.strip_prefix('/').and_then(|path| path.split_once('/'))
+        else {
+            return false;
+        };
+        self.services.contains(service) && !method.is_empty() && !method.contains('/')
+    }
+
+    pub fn into_router(self) -> axum::Router {
+        self.router
+    }
+}
+
+/// Whether `req` has a gRPC or gRPC-Web content type.
+pub fn is_grpc_request<B>(req: &http::Request<B>) -> bool {
+    // also matches `application/grpc-web` and `application/grpc-web-text`
+    req.headers()
+        .get(http::header::CONTENT_TYPE)
+        .is_some_and(|ct| ct.as_bytes().starts_with(b"application/grpc"))
+}
+
+/// Reads `GRPC_REFLECTION`, which enables server reflection when `true`.
+pub fn reflection_enabled_from_env() -> anyhow::Result<bool> {
+    let Ok(enabled) = std::env::var("GRPC_REFLECTION") else {
+        return Ok(false);
+    };
+    enabled
+        .parse()
+        .context("failed to parse GRPC_REFLECTION value")
+}
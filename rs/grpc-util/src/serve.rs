@@ -0,0 +1,677 @@
+use std::{
+    any::Any,
+    fmt, io,
+    net::SocketAddr,
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+
+use anyhow::Context;
+use axum::extract::ConnectInfo;
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, DuplexStream},
+    net::TcpListener,
+    sync::mpsc,
+};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tonic::transport::server::TcpConnectInfo;
+use tower::ServiceExt;
+use tracing::Instrument;
+
+use crate::{
+    shutdown::{self, Shutdown},
+    tls::{PeerIdentity, TlsAcceptor},
+    transport::Http2Config,
+};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Buffer size of each direction of in-memory connections.
+const MEMORY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Address given as [`ConnectInfo`] to requests over Unix domain socket and
+/// in-memory connections.
+const LOCAL_PEER_ADDR: SocketAddr = SocketAddr::V4(std::net::SocketAddrV4::new(
+    std::net::Ipv4Addr::LOCALHOST,
+    0,
+));
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> Io for T {}
+
+// MARK: ListenAddr
+
+/// Address to listen on: `host:port` for TCP, including IPv6 ones like `[::]:4772`,
+/// or `unix:/path` (also `unix:///path`) for a Unix domain socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl std::str::FromStr for ListenAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(path) = s.strip_prefix("unix:") {
+            let path = path.strip_prefix("//").unwrap_or(path);
+            anyhow::ensure!(!path.is_empty(), "Missing Unix socket path in {s}");
+            return Ok(Self::Unix(path.into()));
+        }
+        let addr = s
+            .parse()
+            .with_context(|| format!("Invalid listen address {s}"))?;
+        Ok(Self::Tcp(addr))
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => addr.fmt(f),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Reads `PORT`, falling back to 4772.
+pub fn port_from_env() -> anyhow::Result<u16> {
+    std::env::var("PORT")
+        .unwrap_or_else(|e| {
+            tracing::warn!(
+                error = &e as &dyn std::error::Error,
+                "Failed to load PORT, falling back to default value"
+            );
+            // `grpc` typed on telephone
+            4772.to_string()
+        })
+        .parse()
+        .context("failed to parse PORT value")
+}
+
+/// Reads `LISTEN_ADDRS`, a comma separated list of [`ListenAddr`], falling back to
+/// `0.0.0.0:{PORT}`.
+pub fn listen_addrs_from_env() -> anyhow::Result<Vec<ListenAddr>> {
+    let Ok(addrs) = std::env::var("LISTEN_ADDRS") else {
+        return Ok(vec![ListenAddr::Tcp(
+            ([0, 0, 0, 0], port_from_env()?).into(),
+        )]);
+    };
+    let addrs = addrs
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(str::parse)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("failed to parse LISTEN_ADDRS value")?;
+    anyhow::ensure!(!addrs.is_empty(), "LISTEN_ADDRS is empty");
+    Ok(addrs)
+}
+
+// MARK: Listener
+
+#[derive(Debug)]
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, PathBuf),
+    Memory(mpsc::UnboundedReceiver<DuplexStream>),
+}
+
+impl Listener {
+    async fn bind(addr: &ListenAddr) -> anyhow::Result<Self> {
+        match addr {
+            ListenAddr::Tcp(addr) => {
+                let listener = TcpListener::bind(addr)
+                    .await
+                    .with_context(|| format!("Failed to bind {addr}"))?;
+                Ok(Self::Tcp(listener))
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => {
+                remove_stale_socket(path).await?;
+                let listener = tokio::net::UnixListener::bind(path)
+                    .with_context(|| format!("Failed to bind {}", path.display()))?;
+                Ok(Self::Unix(listener, path.clone()))
+            }
+            #[cfg(not(unix))]
+            ListenAddr::Unix(path) => anyhow::bail!(
+                "Failed to bind {}: Unix domain sockets aren't supported on this platform",
+                path.display()
+            ),
+        }
+    }
+
+    fn listen_addr(&self) -> Option<ListenAddr> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().ok().map(ListenAddr::Tcp),
+            #[cfg(unix)]
+            Self::Unix(_, path) => Some(ListenAddr::Unix(path.clone())),
+            Self::Memory(_) => None,
+        }
+    }
+
+    async fn accept(&mut self) -> io::Result<(Box<dyn Io>, ConnectionInfo)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, peer_addr) = listener.accept().await?;
+                let local_addr = stream.local_addr().ok().map(ListenAddr::Tcp);
+                let info = ConnectionInfo::new(local_addr, Some(peer_addr));
+                Ok((Box::new(stream), info))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener, path) => {
+                let (stream, _) = listener.accept().await?;
+                let info = ConnectionInfo::new(Some(ListenAddr::Unix(path.clone())), None);
+                Ok((Box::new(stream), info))
+            }
+            Self::Memory(connections) => match connections.recv().await {
+                Some(stream) => Ok((Box::new(stream), ConnectionInfo::new(None, None))),
+                // every connector is gone, wait for shutdown
+                None => std::future::pending().await,
+            },
+        }
+    }
+}
+
+/// Removes the socket left at `path` by a previous run, refusing to remove anything
+/// else, or a socket that a server still accepts connections on.
+#[cfg(unix)]
+async fn remove_stale_socket(path: &std::path::Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if tokio::net::UnixStream::connect(path).await.is_ok() {
+                return Err(io::Error::from(io::ErrorKind::AddrInUse))
+                    .with_context(|| format!("Failed to bind {}", path.display()));
+            }
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))
+        }
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to check socket path {}", path.display())),
+    }
+}
+
+/// Accepts a connection on whichever of `listeners` gets one first.
+async fn accept_any(listeners: &mut [Listener]) -> io::Result<(Box<dyn Io>, ConnectionInfo)> {
+    let accepts = listeners.iter_mut().map(|l| Box::pin(l.accept()));
+    futures::future::select_all(accepts).await.0
+}
+
+/// Opens in-memory connections to the [`Server`] returned along with it by
+/// [`Server::in_memory`].
+///
+/// It implements the connector service expected by
+/// [`tonic::transport::Endpoint::connect_with_connector`].
+#[derive(Debug, Clone)]
+pub struct MemoryConnector {
+    connections: mpsc::UnboundedSender<DuplexStream>,
+}
+
+impl MemoryConnector {
+    pub fn connect(&self) -> io::Result<DuplexStream> {
+        let (client, server) = tokio::io::duplex(MEMORY_BUFFER_SIZE);
+        self.connections
+            .send(server)
+            .map_err(|_| io::Error::new(io::ErrorKind::ConnectionRefused, "Server stopped"))?;
+        Ok(client)
+    }
+
+    /// A channel whose connections are made with this connector.
+    pub async fn channel(&self) -> anyhow::Result<tonic::transport::Channel> {
+        tonic::transport::Endpoint::from_static("http://in-memory")
+            .connect_with_connector(self.clone())
+            .await
+            .context("Failed to connect in memory")
+    }
+}
+
+impl tower::Service<http::Uri> for MemoryConnector {
+    type Response = TokioIo<DuplexStream>;
+    type Error = io::Error;
+    type Future = std::future::Ready<io::Result<Self::Response>>;
+
+    fn poll_ready(
+        &mut self,
+        _: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: http::Uri) -> Self::Future {
+        std::future::ready(self.connect().map(TokioIo::new))
+    }
+}
+
+// MARK: Server
+
+/// Accept loop serving HTTP/1.1 and HTTP/2, in plaintext or over TLS.
+///
+/// This replaces `axum::serve`, which can neither terminate TLS nor bound the time
+/// spent draining connections on shutdown. Every request gets the [`ConnectionInfo`]
+/// of its connection. Like `axum::serve`, it also inserts the [`ConnectInfo`] of the
+/// peer address, `127.0.0.1:0` for Unix domain socket peers, and like tonic's
+/// server, the [`TcpConnectInfo`] read by `tonic::Request::remote_addr`.
+///
+/// A server may listen on several addresses at once.
+#[derive(Debug)]
+pub struct Server {
+    listeners: Vec<Listener>,
+    tls: Option<TlsAcceptor>,
+    http2: Http2Config,
+    shutdown: Shutdown,
+    grace_period: Duration,
+}
+
+impl Server {
+    pub async fn bind(addr: SocketAddr) -> anyhow::Result<Self> {
+        Self::bind_all(&[ListenAddr::Tcp(addr)]).await
+    }
+
+    /// Listens on every address of `addrs`. A stale Unix domain socket, left by a
+    /// previous run, is replaced; the socket is removed once the server stops.
+    pub async fn bind_all(addrs: &[ListenAddr]) -> anyhow::Result<Self> {
+        anyhow::ensure!(!addrs.is_empty(), "No address to listen on");
+        let mut listeners = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            listeners.push(Listener::bind(addr).await?);
+        }
+        Ok(Self::new(listeners))
+    }
+
+    pub fn from_listener(listener: TcpListener) -> Self {
+        Self::new(vec![Listener::Tcp(listener)])
+    }
+
+    /// A server accepting the in-memory connections of the returned connector, for
+    /// tests. Requests get `127.0.0.1:0` as peer address.
+    pub fn in_memory() -> (Self, MemoryConnector) {
+        let (connections, receiver) = mpsc::unbounded_channel();
+        let server = Self::new(vec![Listener::Memory(receiver)]);
+        (server, MemoryConnector { connections })
+    }
+
+    fn new(listeners: Vec<Listener>) -> Self {
+        Self {
+            listeners,
+            tls: None,
+            http2: Http2Config::new(),
+            shutdown: Shutdown::new(),
+            grace_period: shutdown::DEFAULT_GRACE_PERIOD,
+        }
+    }
+
+    /// Terminates TLS on every accepted connection when `tls` is `Some`.
+    pub fn tls(self, tls: Option<TlsAcceptor>) -> Self {
+        Self { tls, ..self }
+    }
+
+    pub fn http2(self, http2: Http2Config) -> Self {
+        Self { http2, ..self }
+    }
+
+    /// Once `shutdown` is triggered, stops accepting connections, sends GOAWAY to the
+    /// open ones and waits up to `grace_period` for them to finish.
+    pub fn graceful_shutdown(self, shutdown: Shutdown, grace_period: Duration) -> Self {
+        Self {
+            shutdown,
+            grace_period,
+            ..self
+        }
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    /// Address of the first TCP listener.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listeners
+            .iter()
+            .find_map(|l| match l {
+                Listener::Tcp(listener) => Some(listener.local_addr()),
+                _ => None,
+            })
+            .unwrap_or_else(|| {
+                Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Server has no TCP listener",
+                ))
+            })
+    }
+
+    /// Addresses of every listener, with the ports bound for port 0.
+    pub fn listen_addrs(&self) -> Vec<ListenAddr> {
+        self.listeners
+            .iter()
+            .filter_map(Listener::listen_addr)
+            .collect()
+    }
+
+    pub async fn serve<S, B>(self, service: S) -> anyhow::Result<()>
+    where
+        S: tower::Service<http::Request<axum::body::Body>, Response = http::Response<B>>
+            + Clone
+            + Send
+            + 'static,
+        S::Future: Send,
+        S::Error: Into<BoxError>,
+        B: http_body::Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxError>,
+    {
+        let Self {
+            mut listeners,
+            tls,
+            http2,
+            shutdown,
+            grace_period,
+        } = self;
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        http2.apply_server(&mut builder);
+        let tracker = TaskTracker::new();
+        let force_close = CancellationToken::new();
+        loop {
+            let accepted = tokio::select! {
+                accepted = accept_any(&mut listeners) => accepted,
+                _ = shutdown.triggered() => break,
+            };
+            let (stream, info) = match accepted {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::error!(
+                        error = &e as &dyn std::error::Error,
+                        "Failed to accept connection"
+                    );
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            let span = tracing::debug_span!(
+                "connection",
+                connection_id = info.id(),
+                peer_addr = info.peer_addr().map(tracing::field::display),
+            );
+            let conn = Connection {
+                info,
+                builder: builder.clone(),
+                shutdown: shutdown.clone(),
+                force_close: force_close.clone(),
+            };
+            let tls = tls.clone();
+            let service = service.clone();
+            tracker.spawn(conn.run(stream, tls, service).instrument(span));
+        }
+        let sockets: Vec<PathBuf> = listeners
+            .iter()
+            .filter_map(|l| match l {
+                #[cfg(unix)]
+                Listener::Unix(_, path) => Some(path.clone()),
+                _ => None,
+            })
+            .collect();
+        drop(listeners);
+        for path in sockets {
+            if let Err(e) = std::fs::remove_file(&path) {
+                tracing::warn!(
+                    error = &e as &dyn std::error::Error,
+                    path = %path.display(),
+                    "Failed to remove socket"
+                );
+            }
+        }
+
+        tracing::info!(connections = tracker.len(), "Draining connections");
+        tracker.close();
+        if tokio::time::timeout(grace_period, tracker.wait())
+            .await
+            .is_err()
+        {
+            tracing::warn!(
+                connections = tracker.len(),
+                "Grace period elapsed, closing remaining connections"
+            );
+            force_close.cancel();
+            tracker.wait().await;
+        }
+        tracing::info!("Server stopped");
+        Ok(())
+    }
+}
+
+// MARK: ConnectionInfo
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Connection a request arrived on, inserted into the extensions of every request
+/// served by [`Server`].
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    id: u64,
+    local_addr: Option<ListenAddr>,
+    peer_addr: Option<SocketAddr>,
+    identity: Option<PeerIdentity>,
+    held: Weak<Held>,
+}
+
+impl ConnectionInfo {
+    fn new(local_addr: Option<ListenAddr>, peer_addr: Option<SocketAddr>) -> Self {
+        Self {
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            local_addr,
+            peer_addr,
+            identity: None,
+            held: Weak::new(),
+        }
+    }
+
+    /// Identifier of the connection, unique within the process.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Address the connection was accepted on, `None` in memory.
+    pub fn local_addr(&self) -> Option<&ListenAddr> {
+        self.local_addr.as_ref()
+    }
+
+    /// Address of the TCP peer, `None` over Unix domain sockets and in memory.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Identity of the client certificate on mTLS connections.
+    pub fn identity(&self) -> Option<&PeerIdentity> {
+        self.identity.as_ref()
+    }
+
+    /// Keeps `value` until the connection closes, including once upgraded, e.g. to a
+    /// WebSocket that outlives its request.
+    pub(crate) fn hold_until_closed<T: Send + 'static>(&self, value: T) {
+        if let Some(held) = self.held.upgrade() {
+            held.lock().unwrap().push(Box::new(value));
+        }
+    }
+
+    fn insert_into(&self, extensions: &mut http::Extensions) {
+        extensions.insert(ConnectInfo(self.peer_addr.unwrap_or(LOCAL_PEER_ADDR)));
+        if let Some(remote_addr) = self.peer_addr {
+            let local_addr = match self.local_addr {
+                Some(ListenAddr::Tcp(addr)) => Some(addr),
+                _ => None,
+            };
+            extensions.insert(TcpConnectInfo {
+                local_addr,
+                remote_addr: Some(remote_addr),
+            });
+        }
+        if let Some(identity) = &self.identity {
+            extensions.insert(identity.clone());
+        }
+        extensions.insert(self.clone());
+    }
+}
+
+// MARK: Connection
+
+struct Connection {
+    info: ConnectionInfo,
+    builder: auto::Builder<TokioExecutor>,
+    shutdown: Shutdown,
+    force_close: CancellationToken,
+}
+
+impl Connection {
+    async fn run<S, B>(self, stream: Box<dyn Io>, tls: Option<TlsAcceptor>, service: S)
+    where
+        S: tower::Service<http::Request<axum::body::Body>, Response = http::Response<B>>
+            + Clone
+            + Send
+            + 'static,
+        S::Future: Send,
+        S::Error: Into<BoxError>,
+        B: http_body::Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxError>,
+    {
+        let res = match tls {
+            Some(tls) => {
+                let accepted = tokio::select! {
+                    accepted = tls.accept(stream) => accepted,
+                    _ = self.shutdown.triggered() => return,
+                };
+                let (stream, identity) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!(
+                            error = &e as &dyn std::error::Error,
+                            "TLS handshake failed"
+                        );
+                        return;
+                    }
+                };
+                if let Some(identity) = &identity {
+                    tracing::debug!(%identity, "Client authenticated");
+                }
+                self.serve(stream, service, identity).await
+            }
+            None => self.serve(stream, service, None).await,
+        };
+        if let Err(e) = res {
+            tracing::debug!(error = e, "Connection closed with error");
+        }
+    }
+
+    async fn serve<I, S, B>(
+        &self,
+        io: I,
+        service: S,
+        identity: Option<PeerIdentity>,
+    ) -> Result<(), BoxError>
+    where
+        I: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+        S: tower::Service<http::Request<axum::body::Body>, Response = http::Response<B>>
+            + Clone
+            + Send
+            + 'static,
+        S::Future: Send,
+        S::Error: Into<BoxError>,
+        B: http_body::Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxError>,
+    {
+        let held = Arc::new(Held::default());
+        let info = ConnectionInfo {
+            identity,
+            held: Arc::downgrade(&held),
+            ..self.info.clone()
+        };
+        let io = HoldingIo { io, _held: held };
+        let service = hyper::service::service_fn(move |mut req: http::Request<Incoming>| {
+            info.insert_into(req.extensions_mut());
+            service.clone().oneshot(req.map(axum::body::Body::new))
+        });
+        let conn = self
+            .builder
+            .serve_connection_with_upgrades(TokioIo::new(io), service);
+        tokio::pin!(conn);
+        tokio::select! {
+            res = conn.as_mut() => return res,
+            _ = self.shutdown.triggered() => {}
+        }
+        // Sends GOAWAY on HTTP/2 and lets in-flight requests complete.
+        conn.as_mut().graceful_shutdown();
+        tokio::select! {
+            res = conn => res,
+            _ = self.force_close.cancelled() => {
+                tracing::debug!("Connection closed forcibly");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Values dropped when the IO of a connection is, see
+/// [`ConnectionInfo::hold_until_closed`].
+type Held = Mutex<Vec<Box<dyn Any + Send>>>;
+
+/// IO of a connection, which hyper hands over to the upgraded protocol if any.
+struct HoldingIo<I> {
+    io: I,
+    _held: Arc<Held>,
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for HoldingIo<I> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for HoldingIo<I> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
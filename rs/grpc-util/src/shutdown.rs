@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use futures::{Stream, StreamExt};
+use tokio_util::sync::CancellationToken;
+
+/// Default for `SHUTDOWN_GRACE_PERIOD`.
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Cloneable handle to trigger and observe a server shutdown.
+///
+/// A default handle is never triggered unless [`Shutdown::trigger`] is called.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    token: CancellationToken,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn trigger(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Completes once shutdown has been triggered.
+    pub async fn triggered(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Triggers shutdown on SIGINT or SIGTERM.
+    ///
+    /// If signals can't be listened for, the error is logged and shutdown is left to
+    /// [`Self::trigger`].
+    pub fn trigger_on_signal(self) -> Self {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = signal().await {
+                tracing::error!(
+                    error = &e as &dyn std::error::Error,
+                    "Failed to listen for shutdown signals"
+                );
+                return;
+            }
+            tracing::info!("Received shutdown signal");
+            shutdown.trigger();
+        });
+        self
+    }
+
+    /// Ends `stream` with `UNAVAILABLE` once shutdown has been triggered, so that
+    /// clients of long-lived streams can reconnect elsewhere.
+    pub fn guard_stream<S, T>(&self, stream: S) -> impl Stream<Item = Result<T, tonic::Status>>
+    where
+        S: Stream<Item = Result<T, tonic::Status>>,
+    {
+        let token = self.token.clone();
+        async_stream::stream! {
+            futures::pin_mut!(stream);
+            loop {
+                tokio::select! {
+                    item = stream.next() => match item {
+                        Some(item) => yield item,
+                        None => break,
+                    },
+                    _ = token.cancelled() => {
+                        tracing::debug!("Ending stream for shutdown");
+                        yield Err(shutting_down());
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn shutting_down() -> tonic::Status {
+    use crate::errors::{reasons, ErrorDetails, StatusExt};
+
+    let details = ErrorDetails::with_error_info(
+        reasons::SHUTTING_DOWN,
+        crate::errors::DOMAIN,
+        std::collections::HashMap::new(),
+    );
+    tonic::Status::with_error_details(tonic::Code::Unavailable, "Server is shutting down", details)
+}
+
+/// Completes on SIGINT (Ctrl-C) or, on Unix, SIGTERM.
+pub async fn signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
+
+/// Reads `SHUTDOWN_GRACE_PERIOD` in seconds, falling back to [`DEFAULT_GRACE_PERIOD`].
+pub fn grace_period_from_env() -> anyhow::Result<Duration> {
+    let Ok(secs) = std::env::var("SHUTDOWN_GRACE_PERIOD") else {
+        return Ok(DEFAULT_GRACE_PERIOD);
+    };
+    let secs: f64 = secs
+        .parse()
+        .context("failed to parse SHUTDOWN_GRACE_PERIOD value")?;
+    Duration::try_from_secs_f64(secs).context("Invalid SHUTDOWN_GRACE_PERIOD value")
+}
@@ -0,0 +1,110 @@
+use anyhow::Context;
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tracing_subscriber::{
+    fmt::{format::JsonFields, MakeWriter},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    EnvFilter,
+};
+
+use crate::logging::{JsonFormat, LogFormat};
+
+/// Flushes and stops the span exporter, if any, when dropped.
+#[must_use = "spans are only flushed when this is dropped"]
+#[derive(Debug)]
+pub struct Telemetry {
+    provider: Option<TracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        let Some(provider) = self.provider.take() else {
+            return;
+        };
+        if let Err(e) = provider.shutdown() {
+            tracing::warn!(
+                error = &e as &dyn std::error::Error,
+                "Failed to flush spans"
+            );
+        }
+    }
+}
+
+/// Builds a provider exporting spans in batches over OTLP/gRPC to `endpoint`.
+pub fn tracer_provider(endpoint: &str, service_name: &str) -> anyhow::Result<TracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .context("Failed to build OTLP span exporter")?;
+    let resource = Resource::new([KeyValue::new("service.name", service_name.to_string())]);
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(resource)
+        .build();
+    Ok(provider)
+}
+
+/// Layer turning `tracing` spans into OpenTelemetry spans of `provider`.
+pub fn layer<S>(
+    provider: &TracerProvider,
+) -> tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("grpc-util"))
+}
+
+/// Installs the global subscriber: logs filtered by `RUST_LOG` (`info` by default)
+/// in the [`LogFormat`] read from `LOG_FORMAT` on stdout, and OTLP span export when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+///
+/// Spans are reported under `OTEL_SERVICE_NAME`, or `service_name` when unset. The
+/// same filter applies to exported spans. Must be called within a Tokio runtime.
+pub fn init(service_name: &str) -> anyhow::Result<Telemetry> {
+    init_with_writer(service_name, std::io::stdout)
+}
+
+/// Like [`init`], writing logs to `writer`, e.g. `std::io::stderr` for command line
+/// tools whose output goes to stdout.
+pub fn init_with_writer<W>(service_name: &str, writer: W) -> anyhow::Result<Telemetry>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into());
+    let format = LogFormat::from_env()?;
+    let provider = match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(endpoint) => {
+            let service_name =
+                std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| service_name.to_string());
+            Some(tracer_provider(&endpoint, &service_name)?)
+        }
+        Err(_) => None,
+    };
+    let (text, json) = match format {
+        LogFormat::Text => (
+            Some(tracing_subscriber::fmt::layer().with_writer(writer)),
+            None,
+        ),
+        LogFormat::Json => {
+            let layer = tracing_subscriber::fmt::layer()
+                .fmt_fields(JsonFields::new())
+                .event_format(JsonFormat)
+                .with_writer(writer);
+            (None, Some(layer))
+        }
+    };
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(text)
+        .with(json)
+        .with(provider.as_ref().map(layer))
+        .try_init()
+        .context("Failed to install tracing subscriber")?;
+    if provider.is_some() {
+        tracing::info!("Exporting spans over OTLP");
+    }
+    Ok(Telemetry { provider })
+}
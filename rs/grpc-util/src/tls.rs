@@ -0,0 +1,270 @@
+use std::{fmt, fs, io, path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::rustls::{
+    self,
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+};
+
+fn env_path(key: &str) -> Option<PathBuf> {
+    std::env::var_os(key).map(PathBuf::from)
+}
+
+fn read_certs(path: &PathBuf) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let file = fs::File::open(path)
+        .with_context(|| format!("Failed to open certificate file {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut io::BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse certificates in {}", path.display()))?;
+    anyhow::ensure!(
+        !certs.is_empty(),
+        "No certificate found in {}",
+        path.display()
+    );
+    Ok(certs)
+}
+
+fn read_key(path: &PathBuf) -> anyhow::Result<PrivateKeyDer<'static>> {
+    let file = fs::File::open(path)
+        .with_context(|| format!("Failed to open key file {}", path.display()))?;
+    rustls_pemfile::private_key(&mut io::BufReader::new(file))
+        .with_context(|| format!("Failed to parse private key in {}", path.display()))?
+        .with_context(|| format!("No private key found in {}", path.display()))
+}
+
+// MARK: server
+
+/// PEM files used to terminate TLS on the server side.
+#[derive(Debug, Clone)]
+pub struct ServerTlsConfig {
+    cert: PathBuf,
+    key: PathBuf,
+    client_ca: Option<PathBuf>,
+    client_auth_optional: bool,
+}
+
+impl ServerTlsConfig {
+    pub fn new(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        Self {
+            cert: cert.into(),
+            key: key.into(),
+            client_ca: None,
+            client_auth_optional: false,
+        }
+    }
+
+    /// Verifies client certificates against the CA in `path`.
+    pub fn client_ca(self, path: impl Into<PathBuf>) -> Self {
+        Self {
+            client_ca: Some(path.into()),
+            ..self
+        }
+    }
+
+    /// Accepts clients without a certificate when a client CA is configured.
+    pub fn client_auth_optional(self, optional: bool) -> Self {
+        Self {
+            client_auth_optional: optional,
+            ..self
+        }
+    }
+
+    /// Reads `TLS_CERT`, `TLS_KEY`, `TLS_CLIENT_CA` and `TLS_CLIENT_AUTH`.
+    ///
+    /// Returns `None` when neither `TLS_CERT` nor `TLS_KEY` is set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let (cert, key) = match (env_path("TLS_CERT"), env_path("TLS_KEY")) {
+            (None, None) => return Ok(None),
+            (Some(cert), Some(key)) => (cert, key),
+            _ => anyhow::bail!("TLS_CERT and TLS_KEY must be set together"),
+        };
+        let mut config = Self::new(cert, key);
+        if let Some(client_ca) = env_path("TLS_CLIENT_CA") {
+            config = config.client_ca(client_ca);
+        }
+        let optional = match std::env::var("TLS_CLIENT_AUTH").as_deref() {
+            Ok("optional") => true,
+            Ok("required") | Err(_) => false,
+            Ok(v) => anyhow::bail!("Unknown TLS_CLIENT_AUTH value {v}"),
+        };
+        Ok(Some(config.client_auth_optional(optional)))
+    }
+
+    pub fn build(&self) -> anyhow::Result<TlsAcceptor> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let certs = read_certs(&self.cert)?;
+        let key = read_key(&self.key)?;
+        let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .context("Failed to select TLS protocol versions")?;
+        let builder = match &self.client_ca {
+            Some(client_ca) => {
+                let mut roots = rustls::RootCertStore::empty();
+                for cert in read_certs(client_ca)? {
+                    roots.add(cert).context("Invalid client CA certificate")?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(roots.into(), provider);
+                let verifier = if self.client_auth_optional {
+                    verifier.allow_unauthenticated()
+                } else {
+                    verifier
+                };
+                let verifier = verifier
+                    .build()
+                    .context("Failed to build client certificate verifier")?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder
+            .with_single_cert(certs, key)
+            .context("Invalid server certificate or key")?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        let inner = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+        Ok(TlsAcceptor { inner })
+    }
+}
+
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    inner: tokio_rustls::TlsAcceptor,
+}
+
+impl fmt::Debug for TlsAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsAcceptor").finish_non_exhaustive()
+    }
+}
+
+pub type TlsStream<IO = TcpStream> = tokio_rustls::server::TlsStream<IO>;
+
+impl TlsAcceptor {
+    /// Performs the TLS handshake, returning the verified client identity if any.
+    pub async fn accept<IO>(&self, stream: IO) -> io::Result<(TlsStream<IO>, Option<PeerIdentity>)>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let stream = self.inner.accept(stream).await?;
+        let identity = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(PeerIdentity::from_certificates);
+        Ok((stream, identity))
+    }
+}
+
+// MARK: PeerIdentity
+
+/// Identity of a client that presented a verified certificate.
+///
+/// Inserted into the request extensions of every request on an mTLS connection.
+#[derive(Debug, Clone)]
+pub struct PeerIdentity {
+    common_name: Option<String>,
+    subject_alt_names: Vec<String>,
+    certificates: Arc<[CertificateDer<'static>]>,
+}
+
+impl PeerIdentity {
+    fn from_certificates(certificates: &[CertificateDer<'_>]) -> Option<Self> {
+        use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::*};
+
+        let end_entity = certificates.first()?;
+        let (_, cert) = X509Certificate::from_der(end_entity)
+            .inspect_err(|e| tracing::warn!(error = %e, "Failed to parse peer certificate"))
+            .ok()?;
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_string);
+        let subject_alt_names = cert
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .map(|san| {
+                san.value
+                    .general_names
+                    .iter()
+                    .filter_map(|name| match name {
+                        GeneralName::DNSName(n)
+                        | GeneralName::URI(n)
+                        | GeneralName::RFC822Name(n) => Some(n.to_string()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let certificates = certificates
+            .iter()
+            .map(|c| c.clone().into_owned())
+            .collect();
+        Some(Self {
+            common_name,
+            subject_alt_names,
+            certificates,
+        })
+    }
+
+    pub fn common_name(&self) -> Option<&str> {
+        self.common_name.as_deref()
+    }
+
+    pub fn subject_alt_names(&self) -> &[String] {
+        &self.subject_alt_names
+    }
+
+    /// The certificate chain presented by the peer, end-entity first.
+    pub fn certificates(&self) -> &[CertificateDer<'static>] {
+        &self.certificates
+    }
+
+    /// The common name, or the first subject alternative name if there is none.
+    pub fn name(&self) -> Option<&str> {
+        self.common_name()
+            .or_else(|| self.subject_alt_names.first().map(String::as_str))
+    }
+}
+
+impl fmt::Display for PeerIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name().unwrap_or("<anonymous>"))
+    }
+}
+
+// MARK: client
+
+/// Reads `TLS_CA_CERT`, `TLS_CERT`, `TLS_KEY` and `TLS_DOMAIN`.
+///
+/// Returns `None` when `TLS_CA_CERT` is unset, meaning the client dials in plaintext.
+pub fn client_config_from_env() -> anyhow::Result<Option<tonic::transport::ClientTlsConfig>> {
+    use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+    let Some(ca) = env_path("TLS_CA_CERT") else {
+        return Ok(None);
+    };
+    let ca = fs::read(&ca).with_context(|| format!("Failed to read {}", ca.display()))?;
+    let domain = std::env::var("TLS_DOMAIN").unwrap_or_else(|_| "localhost".to_string());
+    let mut config = ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(ca))
+        .domain_name(domain);
+    match (env_path("TLS_CERT"), env_path("TLS_KEY")) {
+        (None, None) => {}
+        (Some(cert), Some(key)) => {
+            let cert =
+                fs::read(&cert).with_context(|| format!("Failed to read {}", cert.display()))?;
+            let key =
+                fs::read(&key).with_context(|| format!("Failed to read {}", key.display()))?;
+            config = config.identity(Identity::from_pem(cert, key));
+        }
+        _ => anyhow::bail!("TLS_CERT and TLS_KEY must be set together"),
+    }
+    Ok(Some(config))
+}
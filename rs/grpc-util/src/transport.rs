@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use hyper_util::{
+    rt::{TokioExecutor, TokioTimer},
+    server::conn::auto,
+};
+use tonic::transport::Endpoint;
+
+use crate::env_parse;
+
+fn env_secs(key: &str) -> anyhow::Result<Option<Duration>> {
+    let Some(secs) = env_parse::<f64>(key)? else {
+        return Ok(None);
+    };
+    let duration =
+        Duration::try_from_secs_f64(secs).with_context(|| format!("Invalid {key} value"))?;
+    Ok(Some(duration))
+}
+
+// MARK: Http2Config
+
+/// HTTP/2 settings of servers and client channels, hyper's defaults when unset.
+///
+/// Keepalive pings detect dead peers of long-lived streams, such as `RouteChat`,
+/// that would otherwise hang until the OS gives up on the TCP connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Http2Config {
+    keep_alive_interval: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    max_concurrent_streams: Option<u32>,
+    initial_stream_window_size: Option<u32>,
+    initial_connection_window_size: Option<u32>,
+    max_frame_size: Option<u32>,
+    max_header_list_size: Option<u32>,
+}
+
+impl Http2Config {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Pings the peer every `interval` without receiving frames, closing the connection
+    /// when no pong comes back within `timeout` (20 seconds by default).
+    pub fn keep_alive(self, interval: Option<Duration>, timeout: Option<Duration>) -> Self {
+        Self {
+            keep_alive_interval: interval,
+            keep_alive_timeout: timeout,
+            ..self
+        }
+    }
+
+    /// Streams a client may open at once on a server connection.
+    pub fn max_concurrent_streams(self, max: Option<u32>) -> Self {
+        Self {
+            max_concurrent_streams: max,
+            ..self
+        }
+    }
+
+    /// Flow control windows of each stream and of whole connections, in bytes.
+    pub fn initial_window_sizes(self, stream: Option<u32>, connection: Option<u32>) -> Self {
+        Self {
+            initial_stream_window_size: stream,
+            initial_connection_window_size: connection,
+            ..self
+        }
+    }
+
+    /// Largest frame payload the server accepts, in bytes.
+    pub fn max_frame_size(self, size: Option<u32>) -> Self {
+        Self {
+            max_frame_size: size,
+            ..self
+        }
+    }
+
+    /// Largest header list accepted, in bytes.
+    pub fn max_header_list_size(self, size: Option<u32>) -> Self {
+        Self {
+            max_header_list_size: size,
+            ..self
+        }
+    }
+
+    /// Reads `HTTP2_KEEPALIVE_INTERVAL` and `HTTP2_KEEPALIVE_TIMEOUT` in seconds,
+    /// `HTTP2_INITIAL_STREAM_WINDOW_SIZE`, `HTTP2_INITIAL_CONNECTION_WINDOW_SIZE` and
+    /// `HTTP2_MAX_HEADER_LIST_SIZE` in bytes, and for servers only,
+    /// `HTTP2_MAX_CONCURRENT_STREAMS` and `HTTP2_MAX_FRAME_SIZE`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            keep_alive_interval: env_secs("HTTP2_KEEPALIVE_INTERVAL")?,
+            keep_alive_timeout: env_secs("HTTP2_KEEPALIVE_TIMEOUT")?,
+            max_concurrent_streams: env_parse("HTTP2_MAX_CONCURRENT_STREAMS")?,
+            initial_stream_window_size: env_parse("HTTP2_INITIAL_STREAM_WINDOW_SIZE")?,
+            initial_connection_window_size: env_parse("HTTP2_INITIAL_CONNECTION_WINDOW_SIZE")?,
+            max_frame_size: env_parse("HTTP2_MAX_FRAME_SIZE")?,
+            max_header_list_size: env_parse("HTTP2_MAX_HEADER_LIST_SIZE")?,
+        })
+    }
+
+    pub(crate) fn apply_server(&self, builder: &mut auto::Builder<TokioExecutor>) {
+        let mut http2 = builder.http2();
+        // Keepalive pings need a timer.
+        http2
+            .timer(TokioTimer::new())
+            .initial_stream_window_size(self.initial_stream_window_size)
+            .initial_connection_window_size(self.initial_connection_window_size)
+            .max_frame_size(self.max_frame_size);
+        // Unlike the others, these setters overwrite hyper's defaults with `None`.
+        if let Some(interval) = self.keep_alive_interval {
+            http2.keep_alive_interval(interval);
+        }
+        if let Some(max) = self.max_concurrent_streams {
+            http2.max_concurrent_streams(max);
+        }
+        if let Some(timeout) = self.keep_alive_timeout {
+            http2.keep_alive_timeout(timeout);
+        }
+        if let Some(size) = self.max_header_list_size {
+            http2.max_header_list_size(size);
+        }
+    }
+
+    /// Configures a client endpoint. Clients keep pinging while idle, so that
+    /// connections waiting for their next call stay open through proxies.
+    pub fn apply_endpoint(&self, mut endpoint: Endpoint) -> Endpoint {
+        endpoint = endpoint
+            .initial_stream_window_size(self.initial_stream_window_size)
+            .initial_connection_window_size(self.initial_connection_window_size);
+        if let Some(interval) = self.keep_alive_interval {
+            endpoint = endpoint
+                .http2_keep_alive_interval(interval)
+                .keep_alive_while_idle(true);
+        }
+        if let Some(timeout) = self.keep_alive_timeout {
+            endpoint = endpoint.keep_alive_timeout(timeout);
+        }
+        if let Some(size) = self.max_header_list_size {
+            endpoint = endpoint.http2_max_header_list_size(size);
+        }
+        endpoint
+    }
+}
+
+// MARK: MessageSize
+
+/// Size limits of the messages of a service or client, tonic's defaults (4 MiB
+/// decoded, unlimited encoded) when unset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageSize {
+    max_decoding: Option<usize>,
+    max_encoding: Option<usize>,
+}
+
+impl MessageSize {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Largest message received, once decompressed, in bytes. Larger ones fail the
+    /// call with `OUT_OF_RANGE`.
+    pub fn max_decoding(self, size: Option<usize>) -> Self {
+        Self {
+            max_decoding: size,
+            ..self
+        }
+    }
+
+    /// Largest message sent, in bytes.
+    pub fn max_encoding(self, size: Option<usize>) -> Self {
+        Self {
+            max_encoding: size,
+            ..self
+        }
+    }
+
+    /// Reads `GRPC_MAX_DECODING_MESSAGE_SIZE` and `GRPC_MAX_ENCODING_MESSAGE_SIZE`, in
+    /// bytes.
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            max_decoding: env_parse("GRPC_MAX_DECODING_MESSAGE_SIZE")?,
+            max_encoding: env_parse("GRPC_MAX_ENCODING_MESSAGE_SIZE")?,
+        })
+    }
+
+    /// Configures a generated server or client, given its `max_decoding_message_size`
+    /// and `max_encoding_message_size` methods.
+    ///
+    /// ```ignore
+    /// let server = message_size.apply(
+    ///     GreeterServer::new(greeter),
+    ///     GreeterServer::max_decoding_message_size,
+    ///     GreeterServer::max_encoding_message_size,
+    /// );
+    /// ```
+    pub fn apply<T>(
+        &self,
+        target: T,
+        max_decoding_message_size: fn(T, usize) -> T,
+        max_encoding_message_size: fn(T, usize) -> T,
+    ) -> T {
+        let target = match self.max_decoding {
+            Some(size) => max_decoding_message_size(target, size),
+            None => target,
+        };
+        match self.max_encoding {
+            Some(size) => max_encoding_message_size(target, size),
+            None => target,
+        }
+    }
+}
@@ -6,31 +6,33 @@ type Request = tonic::Request<lib::HelloRequest>;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    use tracing_subscriber::EnvFilter;
+    let _telemetry = grpc_util::telemetry::init("helloworld-client")?;
 
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into());
-    tracing_subscriber::fmt().with_env_filter(env_filter).init();
-
-    let port: u16 = std::env::var("PORT")
-        .unwrap_or_else(|e| {
-            tracing::warn!(
-                error = &e as &dyn std::error::Error,
-                "Failed to load PORT, falling back to default value"
-            );
-            // `grpc` typed on telephone
-            4772.to_string()
-        })
-        .parse()
-        .context("failed to parse PORT value")?;
-    let endpoint = format!("http://localhost:{port}");
-    tracing::info!(%endpoint, "Connect client");
-    let mut client = lib::client::GreeterClient::connect(endpoint)
-        .await
-        .context("Failed to connect")?;
+    let channel = grpc_util::client::connect_from_env().await?;
+    let client = grpc_util::compression::Compression::from_env()?.apply(
+        lib::client::GreeterClient::new(channel),
+        lib::client::GreeterClient::send_compressed,
+        lib::client::GreeterClient::accept_compressed,
+    );
+    let mut client = grpc_util::transport::MessageSize::from_env()?.apply(
+        client,
+        lib::client::GreeterClient::max_decoding_message_size,
+        lib::client::GreeterClient::max_encoding_message_size,
+    );
     let request = Request::new(lib::HelloRequest {
         name: "Tonic".to_string(),
     });
-    let response = client.say_hello(request).await.context("Failed to call")?;
+    let response = client
+        .say_hello(request)
+        .await
+        .inspect_err(|status| {
+            tracing::error!(
+                code = %status.code(),
+                message = %status.message(),
+                details = %grpc_util::errors::Details::of(status),
+            )
+        })
+        .context("Failed to call")?;
     tracing::info!(?response, "Received response");
 
     Ok(())
@@ -0,0 +1,49 @@
+use grpc_util::auth::Policy;
+use tonic::{server::NamedService, Request, Response, Status};
+
+use crate::{
+    greeter_server::{Greeter, GreeterServer},
+    HelloReply, HelloRequest,
+};
+
+/// Full name of the `Greeter` gRPC service, as used by health checks.
+pub const SERVICE_NAME: &str = <GreeterServer<MyGreeter> as NamedService>::NAME;
+
+/// Authorization policy used unless `AUTH_POLICY` is set.
+///
+/// `read` and `write` callers may be greeted, and `admin` callers may call anything,
+/// including server reflection. Health checks are public.
+pub fn default_policy() -> Policy {
+    Policy::new()
+        .public("grpc.health.v1.Health/*")
+        .allow("read", format!("{SERVICE_NAME}/*"))
+        .allow("write", format!("{SERVICE_NAME}/*"))
+        .allow("admin", "*")
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MyGreeter;
+
+#[tonic::async_trait]
+impl Greeter for MyGreeter {
+    // The metadata may carry a bearer token, so only the message is recorded.
+    #[tracing::instrument(skip_all, fields(request = ?request.get_ref()))]
+    async fn say_hello(
+        &self,
+        request: Request<HelloRequest>,
+    ) -> Result<Response<HelloReply>, Status> {
+        let (_, extensions, request) = request.into_parts();
+        let peer = extensions.get::<grpc_util::tls::PeerIdentity>();
+        let caller = extensions.get::<grpc_util::auth::Caller>();
+        tracing::info!(
+            peer = peer.map(tracing::field::display),
+            caller = caller.map(tracing::field::display),
+            "Got a request"
+        );
+        let rep_message = format!("Hello, {}!", request.name);
+        let reply = HelloReply {
+            message: rep_message,
+        };
+        Ok(Response::new(reply))
+    }
+}
@@ -1,4 +1,9 @@
 tonic::include_proto!("helloworld");
 
+/// Encoded `FileDescriptorSet` of `helloworld.proto`, for server reflection.
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("helloworld_descriptor");
+
+pub mod greeter;
+
 pub use greeter_client as client;
 pub use greeter_server as server;
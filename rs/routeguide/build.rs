@@ -2,11 +2,13 @@ const PROTO_FILE: &str = "../../protos/route_guide.proto";
 const PROTO_DIR: &str = "../../protos";
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
     tonic_build::configure()
         .build_server(true)
         .build_client(true)
         .build_transport(true)
         .type_attribute(".", "#[derive(serde::Deserialize, serde::Serialize)]")
+        .file_descriptor_set_path(out_dir.join("route_guide_descriptor.bin"))
         .compile_protos(&[PROTO_FILE], &[PROTO_DIR])?;
     Ok(())
 }
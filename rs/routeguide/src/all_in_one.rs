@@ -0,0 +1,113 @@
+//! Any combination of the workspace services on one port, as served by
+//! `grpc-tutor-all-in-one`.
+
+use grpc_util::bootstrap::Bootstrap;
+use tonic_health::ServingStatus;
+
+use crate::server::RouteGuideService;
+
+/// Services enabled by `SERVICES`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Services {
+    pub greeter: bool,
+    pub route_guide: bool,
+    pub admin: bool,
+    pub health: bool,
+    pub reflection: bool,
+    pub http: bool,
+}
+
+impl Services {
+    const DEFAULT: &str = "greeter,routeguide,admin,health,http";
+
+    /// Reads `SERVICES`, a comma separated list of `greeter`, `routeguide`, `admin`
+    /// (`/metrics`), `health`, `reflection` and `http` (the `RouteGuide` REST,
+    /// WebSocket and SSE endpoints), defaulting to all but `reflection`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        std::env::var("SERVICES")
+            .as_deref()
+            .unwrap_or(Self::DEFAULT)
+            .parse()
+    }
+
+    /// Adds the enabled services to `bootstrap`, returning the `RouteGuide` service
+    /// to load features into when `routeguide` or `http` is enabled.
+    ///
+    /// Reflection stays enabled when the `bootstrap` enables it, e.g. with
+    /// `GRPC_REFLECTION`.
+    pub async fn apply(self, bootstrap: Bootstrap) -> (Bootstrap, Option<RouteGuideService>) {
+        let mut bootstrap = bootstrap.health(self.health).admin(self.admin);
+        if self.reflection {
+            bootstrap = bootstrap.reflection(true);
+        }
+        let mut health_reporter = bootstrap.health_reporter();
+        if self.greeter {
+            health_reporter
+                .set_service_status(helloworld::greeter::SERVICE_NAME, ServingStatus::Serving)
+                .await;
+            let greeter = bootstrap
+                .compression()
+                .for_service(helloworld::greeter::SERVICE_NAME)
+                .apply(
+                    helloworld::server::GreeterServer::new(helloworld::greeter::MyGreeter),
+                    helloworld::server::GreeterServer::send_compressed,
+                    helloworld::server::GreeterServer::accept_compressed,
+                );
+            let greeter = bootstrap.message_size().apply(
+                greeter,
+                helloworld::server::GreeterServer::max_decoding_message_size,
+                helloworld::server::GreeterServer::max_encoding_message_size,
+            );
+            bootstrap = bootstrap
+                .add_service(greeter, helloworld::FILE_DESCRIPTOR_SET)
+                .policy(helloworld::greeter::default_policy());
+        }
+        let route_guide = (self.route_guide || self.http).then(|| {
+            RouteGuideService::new()
+                .with_shutdown(bootstrap.shutdown().clone())
+                .with_health(health_reporter.clone())
+                .with_compression(bootstrap.compression().clone())
+                .with_message_size(bootstrap.message_size())
+        });
+        if let Some(route_guide) = &route_guide {
+            health_reporter
+                .set_service_status(crate::server::SERVICE_NAME, ServingStatus::NotServing)
+                .await;
+            // The HTTP endpoints authorize against the `RouteGuide` methods they serve.
+            bootstrap = bootstrap.policy(crate::server::default_policy());
+            if self.route_guide {
+                bootstrap =
+                    bootstrap.add_service(route_guide.clone().build(), crate::FILE_DESCRIPTOR_SET);
+            }
+            if self.http {
+                let http_router = axum::Router::new()
+                    .merge(crate::gateway::router(route_guide.clone()))
+                    .merge(crate::websocket::router(route_guide.clone()))
+                    .merge(crate::sse::router(route_guide.clone()));
+                bootstrap = bootstrap.http(http_router);
+            }
+        }
+        (bootstrap, route_guide)
+    }
+}
+
+impl std::str::FromStr for Services {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut enabled = Self::default();
+        for name in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let flag = match name {
+                "greeter" => &mut enabled.greeter,
+                "routeguide" => &mut enabled.route_guide,
+                "admin" => &mut enabled.admin,
+                "health" => &mut enabled.health,
+                "reflection" => &mut enabled.reflection,
+                "http" => &mut enabled.http,
+                name => anyhow::bail!("Unknown service {name} in SERVICES"),
+            };
+            *flag = true;
+        }
+        Ok(enabled)
+    }
+}
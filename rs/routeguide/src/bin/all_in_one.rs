@@ -0,0 +1,29 @@
+use anyhow::Context;
+
+use routeguide::all_in_one::Services;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let _telemetry = grpc_util::telemetry::init("grpc-tutor-all-in-one")?;
+
+    let services = Services::from_env()?;
+    tracing::info!(?services, "Enabled services");
+    let db_path =
+        std::env::var("ROUTE_GUIDE_DB").unwrap_or_else(|_| "data/route_guide_db.json".to_string());
+    let (bootstrap, route_guide) = services
+        .apply(grpc_util::bootstrap::Bootstrap::from_env()?)
+        .await;
+    let serving = tokio::spawn(bootstrap.serve());
+    if let Some(route_guide) = route_guide {
+        route_guide
+            .runtime_loader()
+            .open(&db_path)
+            .await
+            .with_context(|| format!("Failed to open file {db_path}"))?
+            .load()
+            .await?;
+    }
+    serving.await??;
+
+    Ok(())
+}
@@ -4,61 +4,63 @@ use routeguide as lib;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    use tower::{ServiceBuilder, ServiceExt as _};
-    use tower_http::trace::TraceLayer;
-    use tracing_subscriber::EnvFilter;
+    use tonic_health::ServingStatus;
 
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into());
-    tracing_subscriber::fmt().with_env_filter(env_filter).init();
+    let _telemetry = grpc_util::telemetry::init("routeguide-multiplex")?;
 
     let db_path =
         std::env::var("ROUTE_GUIDE_DB").unwrap_or_else(|_| "data/route_guide_db.json".to_string());
-    let grpc_service = lib::server::RouteGuideService::load(&db_path)?.build();
-    let grpc_service = ServiceBuilder::new()
-        .layer(TraceLayer::new_for_grpc())
-        .service(grpc_service)
-        .map_request(|r: http::Request<_>| r)
-        .map_response(|res| res.map(axum::body::Body::new))
-        .boxed_clone();
-    let http_router = axum::Router::<()>::new()
+    let bootstrap = grpc_util::bootstrap::Bootstrap::from_env()?;
+    let mut health_reporter = bootstrap.health_reporter();
+    health_reporter
+        .set_service_status(lib::server::SERVICE_NAME, ServingStatus::NotServing)
+        .await;
+    health_reporter
+        .set_service_status(helloworld::greeter::SERVICE_NAME, ServingStatus::Serving)
+        .await;
+    let route_guide = lib::server::RouteGuideService::new()
+        .with_shutdown(bootstrap.shutdown().clone())
+        .with_health(health_reporter)
+        .with_compression(bootstrap.compression().clone())
+        .with_message_size(bootstrap.message_size());
+    let greeter = bootstrap
+        .compression()
+        .for_service(helloworld::greeter::SERVICE_NAME)
+        .apply(
+            helloworld::server::GreeterServer::new(helloworld::greeter::MyGreeter),
+            helloworld::server::GreeterServer::send_compressed,
+            helloworld::server::GreeterServer::accept_compressed,
+        );
+    let greeter = bootstrap.message_size().apply(
+        greeter,
+        helloworld::server::GreeterServer::max_decoding_message_size,
+        helloworld::server::GreeterServer::max_encoding_message_size,
+    );
+    // Handlers authorize against the `RouteGuide` method they serve.
+    let http_router = axum::Router::new()
         .route("/ping", axum::routing::get(|| async { "pong".to_string() }))
-        .layer(TraceLayer::new_for_http())
-        .into_service()
-        .boxed_clone();
-    let router = tower::steer::Steer::new(
-        [grpc_service, http_router],
-        |req: &http::Request<_>, _: &[_]| {
-            let p = req
-                .headers()
-                .get(http::header::CONTENT_TYPE)
-                .is_some_and(|ct| ct.as_bytes().starts_with(b"application/grpc"));
-            if p {
-                0
-            } else {
-                1
-            }
-        },
+        .merge(lib::gateway::router(route_guide.clone()))
+        .merge(lib::websocket::router(route_guide.clone()))
+        .merge(lib::sse::router(route_guide.clone()));
+    // Every gRPC service of the workspace, dispatched by path.
+    let serving = tokio::spawn(
+        bootstrap
+            .add_service(route_guide.clone().build(), lib::FILE_DESCRIPTOR_SET)
+            .add_service(greeter, helloworld::FILE_DESCRIPTOR_SET)
+            .policy(lib::server::default_policy())
+            .policy(helloworld::greeter::default_policy())
+            .http(http_router)
+            .admin(true)
+            .serve(),
     );
-
-    let port: u16 = std::env::var("PORT")
-        .unwrap_or_else(|e| {
-            tracing::warn!(
-                error = &e as &dyn std::error::Error,
-                "Failed to load PORT, falling back to default value"
-            );
-            // `grpc` typed on telephone
-            4772.to_string()
-        })
-        .parse()
-        .context("failed to parse PORT value")?;
-    let addr: std::net::SocketAddr = ([0, 0, 0, 0], port).into();
-
-    tracing::info!(%addr, "listening");
-    let listener = tokio::net::TcpListener::bind(addr)
+    route_guide
+        .runtime_loader()
+        .open(&db_path)
         .await
-        .with_context(|| format!("Failed to bind {addr}"))?;
-    let make_service = axum::ServiceExt::into_make_service(router);
-    axum::serve(listener, make_service).await?;
+        .with_context(|| format!("Failed to open file {db_path}"))?
+        .load()
+        .await?;
+    serving.await??;
 
     Ok(())
 }
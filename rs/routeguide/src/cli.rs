@@ -0,0 +1,481 @@
+//! Command line of `routeguide-client`, calling every `RouteGuide` RPC.
+//!
+//! Coordinates are `LAT,LON` pairs in decimal degrees, or in the E7 representation
+//! of `route_guide.proto` with `--e7`. Results are printed as text, as JSON (one
+//! document per command) or as NDJSON (one message per line).
+
+use std::{future::Future, io::Write, path::PathBuf};
+
+use anyhow::Context;
+use futures::{StreamExt, TryStreamExt};
+use grpc_util::errors::Details;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+use tonic::Status;
+
+use crate::{
+    coordinates::{format_point, Coordinates, Unit},
+    track::TrackPoint,
+    Client, Feature, Point, Rectangle, RouteNote, RouteSummary,
+};
+
+/// Attempts of calls rejected with a retry delay, e.g. by rate limits.
+const MAX_ATTEMPTS: u32 = 3;
+
+// MARK: Cli
+
+#[derive(Debug, clap::Parser)]
+#[command(
+    name = "routeguide-client",
+    about = "Calls the RouteGuide service at GRPC_ENDPOINT (localhost:PORT by default)"
+)]
+pub struct Cli {
+    /// Output format.
+    #[arg(long, value_enum, default_value_t, global = true)]
+    pub format: Format,
+    /// Reads and prints coordinates in E7 (degrees times 10^7) instead of decimal
+    /// degrees.
+    #[arg(long, global = true)]
+    pub e7: bool,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// Gets the feature at a point.
+    GetFeature {
+        #[arg(value_name = "LAT,LON", allow_hyphen_values = true)]
+        point: Coordinates,
+    },
+    /// Lists the features within a rectangle, given two opposite corners.
+    ListFeatures {
+        #[arg(value_name = "LAT,LON", allow_hyphen_values = true)]
+        lo: Coordinates,
+        #[arg(value_name = "LAT,LON", allow_hyphen_values = true)]
+        hi: Coordinates,
+    },
+    /// Records a route read from a file or a GPS track, or sampled at random from
+    /// the feature database.
+    RecordRoute {
+        /// `LAT,LON` lines, or a JSON array of points or features in E7; `-` for
+        /// stdin.
+        #[arg(long, conflicts_with_all = ["sample", "track"])]
+        file: Option<PathBuf>,
+        /// GPX track or GeoJSON `LineString`, in degrees.
+        #[arg(long, conflicts_with = "sample")]
+        track: Option<PathBuf>,
+        /// Sends the points of the track at the pace of its timestamps.
+        #[arg(long, requires = "track")]
+        realtime: bool,
+        /// Speed multiplier of `--realtime`, e.g. 10 to replay 10 times faster.
+        #[arg(long, requires = "realtime", default_value_t = 1.0)]
+        speed: f64,
+        /// Number of features sampled from the database, with replacement.
+        #[arg(long, default_value_t = 10)]
+        sample: usize,
+        /// Feature database to sample from.
+        #[arg(
+            long,
+            env = "ROUTE_GUIDE_DB",
+            default_value = "data/route_guide_db.json"
+        )]
+        db: PathBuf,
+    },
+    /// Sends the notes read from stdin as `LAT,LON message` lines, and prints the
+    /// notes received at their locations.
+    RouteChat,
+    /// Chats interactively: messages typed are sent at the current location, and
+    /// the notes received are printed as they come.
+    Chat {
+        /// Initial location, see the `/at` command.
+        #[arg(long, value_name = "LAT,LON", allow_hyphen_values = true)]
+        at: Option<Coordinates>,
+    },
+}
+
+impl Cli {
+    pub fn unit(&self) -> Unit {
+        if self.e7 {
+            Unit::E7
+        } else {
+            Unit::Degrees
+        }
+    }
+
+    /// Runs the command, reading notes or a route from `input` and printing the
+    /// results to `writer`.
+    pub async fn run<R, W>(self, client: &mut Client, mut input: R, writer: W) -> anyhow::Result<()>
+    where
+        R: AsyncBufRead + Unpin + Send + 'static,
+        W: Write,
+    {
+        let unit = self.unit();
+        let mut output = Output::new(self.format, unit, writer);
+        match self.command {
+            Command::GetFeature { point } => {
+                get_feature(client, point.point(unit)?, &mut output).await
+            }
+            Command::ListFeatures { lo, hi } => {
+                let rect = Rectangle {
+                    lo: Some(lo.point(unit)?),
+                    hi: Some(hi.point(unit)?),
+                };
+                list_features(client, rect, &mut output).await
+            }
+            Command::RecordRoute {
+                file,
+                track,
+                realtime,
+                speed,
+                sample,
+                db,
+            } => {
+                if let Some(path) = track {
+                    anyhow::ensure!(
+                        speed.is_finite() && speed > 0.0,
+                        "Invalid speed {speed}, expected a positive number"
+                    );
+                    let track = crate::track::load(&path)?;
+                    let speed = realtime.then_some(speed);
+                    return record_route(client, track, speed, &mut output).await;
+                }
+                let points = match file {
+                    Some(path) if path.as_os_str() == "-" => {
+                        let mut text = String::new();
+                        input.read_to_string(&mut text).await?;
+                        parse_route(&text, unit)?
+                    }
+                    Some(path) => {
+                        let text = std::fs::read_to_string(&path)
+                            .with_context(|| format!("Failed to read {}", path.display()))?;
+                        parse_route(&text, unit)
+                            .with_context(|| format!("Failed to parse {}", path.display()))?
+                    }
+                    None => sample_route(&db, sample)?,
+                };
+                let route = points.into_iter().map(TrackPoint::from).collect();
+                record_route(client, route, None, &mut output).await
+            }
+            Command::RouteChat => route_chat(client, input, unit, &mut output).await,
+            Command::Chat { at } => {
+                anyhow::ensure!(self.format == Format::Text, "Chat only prints text");
+                let location = at.map(|c| c.point(unit)).transpose()?;
+                crate::repl::Repl::new(unit, location, output.writer)
+                    .run(client, input)
+                    .await
+            }
+        }
+    }
+}
+
+// MARK: Parsing
+
+/// Parses a route: a JSON array of points or features in E7, as in the feature
+/// database, or `LAT,LON` lines in `unit`, skipping blank lines and `#` comments.
+pub fn parse_route(text: &str, unit: Unit) -> anyhow::Result<Vec<Point>> {
+    if text.trim_start().starts_with('[') {
+        if let Ok(points) = serde_json::from_str::<Vec<Point>>(text) {
+            return Ok(points);
+        }
+        let features: Vec<Feature> =
+            serde_json::from_str(text).context("Expected a JSON array of points or features")?;
+        return Ok(features.into_iter().filter_map(|f| f.location).collect());
+    }
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| {
+            line.parse::<Coordinates>()
+                .and_then(|c| c.point(unit))
+                .with_context(|| format!("Invalid point on line {}", i + 1))
+        })
+        .collect()
+}
+
+/// Picks `count` feature locations of the database at `db`, at random.
+fn sample_route(db: &std::path::Path, count: usize) -> anyhow::Result<Vec<Point>> {
+    use rand::seq::SliceRandom;
+
+    let features = Feature::db_loader()
+        .open(db)
+        .with_context(|| format!("Failed to open file {}", db.display()))?
+        .load()
+        .context("Failed to parse features JSON")?;
+    let points: Vec<_> = features.iter().filter_map(|f| f.location).collect();
+    anyhow::ensure!(!points.is_empty(), "No feature in {}", db.display());
+    let mut rng = rand::thread_rng();
+    Ok((0..count)
+        .filter_map(|_| points.choose(&mut rng).copied())
+        .collect())
+}
+
+/// Parses a `LAT,LON message` line.
+pub fn parse_note(line: &str, unit: Unit) -> anyhow::Result<RouteNote> {
+    let line = line.trim();
+    let (coordinates, message) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let location = coordinates.parse::<Coordinates>()?.point(unit)?;
+    Ok(RouteNote {
+        location: Some(location),
+        message: message.trim().to_string(),
+    })
+}
+
+// MARK: Output
+
+/// Output format of the results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// One line per message, with coordinates in the unit of the command line.
+    #[default]
+    Text,
+    /// One pretty-printed document: an object for unary calls, an array for streams.
+    Json,
+    /// One JSON message per line.
+    Ndjson,
+}
+
+/// Message printable as text.
+pub(crate) trait Text: serde::Serialize {
+    fn text(&self, unit: Unit) -> String;
+}
+
+impl Text for Feature {
+    fn text(&self, unit: Unit) -> String {
+        let location = self.location.as_ref().map(|p| format_point(p, unit));
+        let name = if self.name.is_empty() {
+            "(unnamed)"
+        } else {
+            &self.name
+        };
+        format!("{}\t{name}", location.unwrap_or_default())
+    }
+}
+
+impl Text for RouteNote {
+    fn text(&self, unit: Unit) -> String {
+        let location = self.location.as_ref().map(|p| format_point(p, unit));
+        format!("{}\t{}", location.unwrap_or_default(), self.message)
+    }
+}
+
+impl Text for RouteSummary {
+    fn text(&self, _: Unit) -> String {
+        format!(
+            "points: {}\nfeatures: {}\ndistance: {}\nelapsed time: {}s",
+            self.point_count, self.feature_count, self.distance, self.elapsed_time
+        )
+    }
+}
+
+/// Prints results in a [`Format`], as they come.
+struct Output<W> {
+    format: Format,
+    unit: Unit,
+    writer: W,
+    /// Messages of the stream being printed as a JSON array.
+    items: Vec<serde_json::Value>,
+}
+
+impl<W: Write> Output<W> {
+    fn new(format: Format, unit: Unit, writer: W) -> Self {
+        Self {
+            format,
+            unit,
+            writer,
+            items: Vec::new(),
+        }
+    }
+
+    /// Prints the response of a unary call.
+    fn one(&mut self, message: &impl Text) -> anyhow::Result<()> {
+        match self.format {
+            Format::Text => writeln!(self.writer, "{}", message.text(self.unit))?,
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut self.writer, message)?;
+                writeln!(self.writer)?;
+            }
+            Format::Ndjson => {
+                serde_json::to_writer(&mut self.writer, message)?;
+                writeln!(self.writer)?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Prints the missing response of a unary call, as `null` in JSON.
+    fn none(&mut self) -> anyhow::Result<()> {
+        if self.format != Format::Text {
+            writeln!(self.writer, "null")?;
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Prints a message of a stream, or keeps it for [`Self::finish`] in JSON.
+    fn item(&mut self, message: &impl Text) -> anyhow::Result<()> {
+        if self.format == Format::Json {
+            self.items.push(serde_json::to_value(message)?);
+            return Ok(());
+        }
+        self.one(message)
+    }
+
+    /// Ends a stream, printing the JSON array.
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if self.format == Format::Json {
+            serde_json::to_writer_pretty(&mut self.writer, &self.items)?;
+            writeln!(self.writer)?;
+            self.writer.flush()?;
+            self.items.clear();
+        }
+        Ok(())
+    }
+}
+
+// MARK: Calls
+
+pub(crate) fn log_status(status: &Status) {
+    tracing::error!(
+        code = %status.code(),
+        message = %status.message(),
+        details = %Details::of(status),
+    );
+}
+
+/// Calls `call` until it succeeds, or fails without a retry delay or for the last
+/// time.
+async fn retrying<T, F, Fut>(mut call: F) -> Result<T, Status>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Status>>,
+{
+    let mut attempt = 1;
+    loop {
+        let status = match call().await {
+            Ok(response) => return Ok(response),
+            Err(status) => status,
+        };
+        match grpc_util::errors::retry_delay(&status) {
+            Some(delay) if attempt < MAX_ATTEMPTS => {
+                tracing::info!(?delay, attempt, "Retrying");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            _ => return Err(status),
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(otel.kind = "client", request_id, trace_id))]
+async fn get_feature(
+    client: &mut Client,
+    point: Point,
+    output: &mut Output<impl Write>,
+) -> anyhow::Result<()> {
+    let response = retrying(|| {
+        let mut client = client.clone();
+        async move { client.get_feature(point).await }
+    })
+    .await;
+    let feature = match response {
+        Ok(response) => response.into_inner(),
+        Err(status)
+            if grpc_util::errors::reason(&status).as_deref()
+                == Some(crate::server::reasons::FEATURE_NOT_FOUND) =>
+        {
+            tracing::info!(
+                point = format_point(&point, output.unit),
+                "No feature at this point"
+            );
+            return output.none();
+        }
+        Err(status) => {
+            log_status(&status);
+            return Err(status).context("Server responded with error");
+        }
+    };
+    output.one(&feature)
+}
+
+#[tracing::instrument(skip_all, fields(otel.kind = "client", request_id, trace_id))]
+async fn list_features(
+    client: &mut Client,
+    rect: Rectangle,
+    output: &mut Output<impl Write>,
+) -> anyhow::Result<()> {
+    let mut features = retrying(|| {
+        let mut client = client.clone();
+        async move { client.list_features(rect).await }
+    })
+    .await
+    .inspect_err(log_status)
+    .context("Server responded with error")?
+    .into_inner();
+    while let Some(feature) = features
+        .try_next()
+        .await
+        .inspect_err(log_status)
+        .context("Server yielded error status")?
+    {
+        output.item(&feature)?;
+    }
+    output.finish()
+}
+
+#[tracing::instrument(skip_all, fields(otel.kind = "client", request_id, trace_id, points = route.len(), speed))]
+async fn record_route(
+    client: &mut Client,
+    route: Vec<TrackPoint>,
+    speed: Option<f64>,
+    output: &mut Output<impl Write>,
+) -> anyhow::Result<()> {
+    let summary = client
+        .record_route(crate::track::replay(route, speed))
+        .await
+        .inspect_err(log_status)
+        .context("Server responded with error")?
+        .into_inner();
+    output.one(&summary)
+}
+
+#[tracing::instrument(skip_all, fields(otel.kind = "client", request_id, trace_id))]
+async fn route_chat<R>(
+    client: &mut Client,
+    input: R,
+    unit: Unit,
+    output: &mut Output<impl Write>,
+) -> anyhow::Result<()>
+where
+    R: AsyncBufRead + Unpin + Send + 'static,
+{
+    let lines = tokio_stream::wrappers::LinesStream::new(input.lines());
+    let notes = lines.filter_map(move |line| async move {
+        let line = line
+            .inspect_err(|e| {
+                tracing::error!(error = e as &dyn std::error::Error, "Failed to read notes")
+            })
+            .ok()?;
+        if line.trim().is_empty() {
+            return None;
+        }
+        parse_note(&line, unit)
+            .inspect_err(|e| tracing::warn!(error = %format!("{e:#}"), line, "Skipping note"))
+            .ok()
+    });
+    let mut notes = client
+        .route_chat(notes)
+        .await
+        .inspect_err(log_status)
+        .context("Server responded with error")?
+        .into_inner();
+    while let Some(note) = notes
+        .try_next()
+        .await
+        .inspect_err(log_status)
+        .context("Server yielded error status")?
+    {
+        output.item(&note)?;
+    }
+    output.finish()
+}
@@ -0,0 +1,78 @@
+//! Coordinates in decimal degrees or in the E7 representation of
+//! `route_guide.proto`, shared by the command line and track replays.
+
+use anyhow::Context;
+
+use crate::Point;
+
+/// Unit of the coordinates read and printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Degrees,
+    E7,
+}
+
+/// `LAT,LON` pair, in a [`Unit`] given by the command line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl std::str::FromStr for Coordinates {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (latitude, longitude) = s
+            .split_once(',')
+            .with_context(|| format!("Expected LAT,LON, got {s}"))?;
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<f64>()
+                .with_context(|| format!("Invalid coordinate {v}"))
+        };
+        Ok(Self {
+            latitude: parse(latitude)?,
+            longitude: parse(longitude)?,
+        })
+    }
+}
+
+impl Coordinates {
+    pub fn point(&self, unit: Unit) -> anyhow::Result<Point> {
+        Ok(Point {
+            latitude: to_e7(self.latitude, unit)?,
+            longitude: to_e7(self.longitude, unit)?,
+        })
+    }
+}
+
+fn to_e7(value: f64, unit: Unit) -> anyhow::Result<i32> {
+    let e7 = match unit {
+        Unit::Degrees => (value * 1e7).round(),
+        Unit::E7 => {
+            anyhow::ensure!(
+                value.fract() == 0.0,
+                "E7 coordinates are integers, got {value}"
+            );
+            value
+        }
+    };
+    anyhow::ensure!(
+        (i32::MIN as f64..=i32::MAX as f64).contains(&e7),
+        "Coordinate {value} out of range"
+    );
+    Ok(e7 as i32)
+}
+
+/// Formats `point` as `LAT,LON` in `unit`, as read by [`Coordinates`].
+pub fn format_point(point: &Point, unit: Unit) -> String {
+    match unit {
+        Unit::Degrees => format!(
+            "{:.7},{:.7}",
+            point.latitude as f64 / 1e7,
+            point.longitude as f64 / 1e7
+        ),
+        Unit::E7 => format!("{},{}", point.latitude, point.longitude),
+    }
+}
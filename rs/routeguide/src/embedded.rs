@@ -0,0 +1,124 @@
+use std::net::SocketAddr;
+
+use anyhow::Context;
+use grpc_util::{bootstrap::Bootstrap, client::RequestMetadata, serve::Server, shutdown::Shutdown};
+use tokio::task::JoinHandle;
+use tonic::{service::interceptor::InterceptedService, transport::Channel};
+
+use crate::{route_guide_client::RouteGuideClient, server::RouteGuideService, Client};
+
+/// `RouteGuide` served in-process with the full layer stack of `routeguide-server`,
+/// for hermetic integration tests.
+///
+/// ```ignore
+/// let server = EmbeddedServer::start(Bootstrap::new(), DB_PATH).await?;
+/// let feature = server.client().get_feature(point).await?;
+/// server.shutdown().await?;
+/// ```
+#[derive(Debug)]
+pub struct EmbeddedServer {
+    addr: Option<SocketAddr>,
+    channel: Channel,
+    service: RouteGuideService,
+    shutdown: Shutdown,
+    serving: JoinHandle<anyhow::Result<()>>,
+}
+
+impl EmbeddedServer {
+    /// Serves the features of the JSON file at `db_path` on an ephemeral port of
+    /// `127.0.0.1`, returning once they are loaded.
+    pub async fn start(bootstrap: Bootstrap, db_path: &str) -> anyhow::Result<Self> {
+        let server = Server::bind(([127, 0, 0, 1], 0).into()).await?;
+        let addr = server.local_addr()?;
+        let endpoint = format!("http://{addr}");
+        let connect = async move { Ok(Channel::from_shared(endpoint)?.connect().await?) };
+        Self::start_on(bootstrap, db_path, server, Some(addr), connect).await
+    }
+
+    /// Like [`Self::start`], over in-memory connections instead of TCP.
+    pub async fn start_in_memory(bootstrap: Bootstrap, db_path: &str) -> anyhow::Result<Self> {
+        let (server, connector) = Server::in_memory();
+        let connect = async move { connector.channel().await };
+        Self::start_on(bootstrap, db_path, server, None, connect).await
+    }
+
+    /// Serves on `server`, then awaits `connect` once the features are loaded.
+    async fn start_on(
+        bootstrap: Bootstrap,
+        db_path: &str,
+        server: Server,
+        addr: Option<SocketAddr>,
+        connect: impl std::future::Future<Output = anyhow::Result<Channel>>,
+    ) -> anyhow::Result<Self> {
+        let service = RouteGuideService::new()
+            .with_shutdown(bootstrap.shutdown().clone())
+            .with_health(bootstrap.health_reporter())
+            .with_compression(bootstrap.compression().clone())
+            .with_message_size(bootstrap.message_size());
+        let shutdown = bootstrap.shutdown().clone();
+        let serving = tokio::spawn(
+            bootstrap
+                .add_service(service.clone().build(), crate::FILE_DESCRIPTOR_SET)
+                .policy(crate::server::default_policy())
+                .serve_on(server),
+        );
+        let started = async {
+            service
+                .runtime_loader()
+                .open(db_path)
+                .await
+                .with_context(|| format!("Failed to open file {db_path}"))?
+                .load()
+                .await?;
+            connect.await
+        };
+        let channel = match started.await {
+            Ok(channel) => channel,
+            Err(e) => {
+                shutdown.trigger();
+                return Err(e);
+            }
+        };
+        Ok(Self {
+            addr,
+            channel,
+            service,
+            shutdown,
+            serving,
+        })
+    }
+
+    /// Bound address, `None` in memory.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+
+    /// A connected channel, e.g. for clients of other services or with other
+    /// interceptors.
+    pub fn channel(&self) -> Channel {
+        self.channel.clone()
+    }
+
+    /// A client sending request IDs and trace context, like the `routeguide-client`
+    /// binary, over [`Self::channel`].
+    pub fn client(&self) -> Client {
+        let channel = InterceptedService::new(self.channel(), RequestMetadata::default());
+        RouteGuideClient::new(channel)
+    }
+
+    /// The served service, e.g. to add features.
+    pub fn service(&self) -> &RouteGuideService {
+        &self.service
+    }
+
+    /// Handle triggering the graceful shutdown of the server.
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Triggers shutdown and waits for the server to stop.
+    pub async fn shutdown(self) -> anyhow::Result<()> {
+        self.shutdown.trigger();
+        self.serving.await?
+    }
+}
@@ -0,0 +1,177 @@
+//! REST/JSON gateway exposing [`RouteGuideService`] over plain HTTP.
+//!
+//! - `GET /v1/features?lat=&lon=` returns the [`Feature`](crate::Feature) at a point.
+//! - `GET /v1/features?lo_lat=&lo_lon=&hi_lat=&hi_lon=` streams the features in a
+//!   rectangle as NDJSON.
+//! - `POST /v1/routes` takes a JSON array of points and returns the
+//!   [`RouteSummary`](crate::RouteSummary).
+//!
+//! Coordinates are in the E7 representation, as in `route_guide.proto`.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::StreamExt;
+use tonic::{metadata::MetadataMap, Code, Status};
+
+use crate::{
+    route_guide_server::RouteGuide,
+    server::{methods, RouteGuideService},
+};
+
+pub fn router(service: RouteGuideService) -> axum::Router {
+    axum::Router::new()
+        .route("/v1/features", axum::routing::get(features))
+        .route("/v1/routes", axum::routing::post(record_route))
+        .with_state(service)
+}
+
+/// Builds a gRPC request carrying the headers and extensions of the HTTP request.
+fn grpc_request<T>(parts: Parts, message: T) -> tonic::Request<T> {
+    let metadata = MetadataMap::from_headers(parts.headers);
+    tonic::Request::from_parts(metadata, parts.extensions, message)
+}
+
+// MARK: handlers
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+pub(crate) struct FeaturesQuery {
+    lat: Option<i32>,
+    lon: Option<i32>,
+    lo_lat: Option<i32>,
+    lo_lon: Option<i32>,
+    hi_lat: Option<i32>,
+    hi_lon: Option<i32>,
+}
+
+impl FeaturesQuery {
+    fn point(&self) -> Option<crate::Point> {
+        let (latitude, longitude) = self.lat.zip(self.lon)?;
+        Some(crate::Point {
+            latitude,
+            longitude,
+        })
+    }
+
+    pub(crate) fn rectangle(&self) -> Option<crate::Rectangle> {
+        let (lo_lat, lo_lon) = self.lo_lat.zip(self.lo_lon)?;
+        let (hi_lat, hi_lon) = self.hi_lat.zip(self.hi_lon)?;
+        let point = |latitude, longitude| crate::Point {
+            latitude,
+            longitude,
+        };
+        Some(crate::Rectangle {
+            lo: Some(point(lo_lat, lo_lon)),
+            hi: Some(point(hi_lat, hi_lon)),
+        })
+    }
+}
+
+#[tracing::instrument(skip(service, parts))]
+async fn features(
+    State(service): State<RouteGuideService>,
+    Query(query): Query<FeaturesQuery>,
+    parts: Parts,
+) -> Result<Response, GatewayError> {
+    if let Some(point) = query.point() {
+        grpc_util::auth::authorize(&parts.extensions, methods::GET_FEATURE)?;
+        let feature = service.get_feature(grpc_request(parts, point)).await?;
+        return Ok(Json(feature.into_inner()).into_response());
+    }
+    let Some(rect) = query.rectangle() else {
+        let status = Status::invalid_argument(
+            "Either lat and lon, or lo_lat, lo_lon, hi_lat and hi_lon are required",
+        );
+        return Err(status.into());
+    };
+    grpc_util::auth::authorize(&parts.extensions, methods::LIST_FEATURES)?;
+    let features = service
+        .list_features(grpc_request(parts, rect))
+        .await?
+        .into_inner();
+    // An error ends the stream with a final `{"code": ..., "message": ...}` line.
+    let lines = futures::stream::unfold(Some(features), |features| async move {
+        let mut features = features?;
+        match features.next().await? {
+            Ok(feature) => Some((ndjson_line(&feature), Some(features))),
+            Err(status) => Some((ndjson_line(&ErrorBody::from(&status)), None)),
+        }
+    });
+    let body = axum::body::Body::from_stream(lines);
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
+
+fn ndjson_line<T: serde::Serialize>(value: &T) -> Result<String, serde_json::Error> {
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    Ok(line)
+}
+
+#[tracing::instrument(skip_all)]
+async fn record_route(
+    State(service): State<RouteGuideService>,
+    parts: Parts,
+    Json(points): Json<Vec<crate::Point>>,
+) -> Result<Json<crate::RouteSummary>, GatewayError> {
+    grpc_util::auth::authorize(&parts.extensions, methods::RECORD_ROUTE)?;
+    let points = futures::stream::iter(points.into_iter().map(Ok));
+    let summary = service.record_points(grpc_request(parts, points)).await?;
+    Ok(Json(summary.into_inner()))
+}
+
+// MARK: GatewayError
+
+/// Responds with the HTTP status corresponding to a gRPC status, and a JSON body.
+#[derive(Debug)]
+pub struct GatewayError(pub Status);
+
+impl From<Status> for GatewayError {
+    fn from(value: Status) -> Self {
+        Self(value)
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct ErrorBody<'a> {
+    code: i32,
+    message: &'a str,
+}
+
+impl<'a> From<&'a Status> for ErrorBody<'a> {
+    fn from(value: &'a Status) -> Self {
+        Self {
+            code: value.code() as i32,
+            message: value.message(),
+        }
+    }
+}
+
+impl IntoResponse for GatewayError {
+    fn into_response(self) -> Response {
+        let status = http_status(self.0.code());
+        (status, Json(ErrorBody::from(&self.0))).into_response()
+    }
+}
+
+/// Maps a gRPC status code to an HTTP status, following `google.rpc.Code`.
+pub fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::Cancelled => StatusCode::from_u16(499).unwrap(),
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
@@ -1,5 +1,22 @@
 tonic::include_proto!("route_guide");
 
+/// Encoded `FileDescriptorSet` of `route_guide.proto`, for server reflection.
+pub const FILE_DESCRIPTOR_SET: &[u8] =
+    tonic::include_file_descriptor_set!("route_guide_descriptor");
+
+/// `RouteGuide` client over a channel sending request metadata.
+pub type Client = route_guide_client::RouteGuideClient<grpc_util::client::Channel>;
+
+pub mod all_in_one;
+pub mod cli;
+pub mod coordinates;
 pub mod data;
+pub mod embedded;
+pub mod gateway;
+mod metrics;
+pub mod repl;
 pub mod server;
+pub mod sse;
+pub mod track;
 mod util;
+pub mod websocket;
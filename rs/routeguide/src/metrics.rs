@@ -0,0 +1,46 @@
+use std::sync::LazyLock;
+
+use prometheus::{
+    register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge,
+    Histogram, IntCounter, IntCounterVec, IntGauge,
+};
+
+pub(crate) static FEATURES: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "route_guide_features",
+        "Number of features in the database."
+    )
+    .unwrap()
+});
+
+pub(crate) static LOADS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "route_guide_loads_total",
+        "Number of database loads, by result.",
+        &["result"]
+    )
+    .unwrap()
+});
+
+pub(crate) static LOADED_FEATURES: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "route_guide_loaded_features_total",
+        "Number of features added by database loads."
+    )
+    .unwrap()
+});
+
+pub(crate) static LOAD_SECONDS: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "route_guide_load_duration_seconds",
+        "Time taken by each database load."
+    )
+    .unwrap()
+});
+
+/// Records the outcome of a database load that took `elapsed`.
+pub(crate) fn record_load<T, E>(res: &Result<T, E>, elapsed: std::time::Duration) {
+    let result = if res.is_ok() { "success" } else { "failure" };
+    LOADS.with_label_values(&[result]).inc();
+    LOAD_SECONDS.observe(elapsed.as_secs_f64());
+}
@@ -0,0 +1,164 @@
+//! Interactive `RouteChat`: notes typed at a current location are sent over one
+//! stream, while the notes received are printed as they come.
+
+use std::io::Write;
+
+use anyhow::Context;
+use futures::TryStreamExt;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::{
+    cli::{log_status, Text},
+    coordinates::{format_point, Coordinates, Unit},
+    Client, Point, RouteNote,
+};
+
+const HELP: &str = "\
+Type a message to send it at the current location, or a command:
+  /at LAT,LON   moves to a location (also /move), /at alone prints it
+  /history      lists the notes sent and received
+  /help         prints this message
+  /quit         closes the chat, as does end of input";
+
+/// What to do after a line of input.
+enum Action {
+    Nothing,
+    Send(RouteNote),
+    Quit,
+}
+
+enum Entry {
+    Sent(RouteNote),
+    Received(RouteNote),
+}
+
+/// Chat session, reading messages and commands from `input` and printing to
+/// `writer`.
+pub struct Repl<W> {
+    unit: Unit,
+    location: Option<Point>,
+    history: Vec<Entry>,
+    writer: W,
+}
+
+impl<W: Write> Repl<W> {
+    pub fn new(unit: Unit, location: Option<Point>, writer: W) -> Self {
+        Self {
+            unit,
+            location,
+            history: Vec::new(),
+            writer,
+        }
+    }
+
+    /// Opens the `RouteChat` stream and runs the session until `/quit` or the end of
+    /// `input`, then prints the notes the server still sends.
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", request_id, trace_id))]
+    pub async fn run<R>(mut self, client: &mut Client, input: R) -> anyhow::Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        let mut notes = client
+            .route_chat(tokio_stream::wrappers::ReceiverStream::new(rx))
+            .await
+            .inspect_err(log_status)
+            .context("Server responded with error")?
+            .into_inner();
+        writeln!(self.writer, "Connected, /help for commands")?;
+        self.writer.flush()?;
+        let mut tx = Some(tx);
+        let mut lines = input.lines();
+        loop {
+            tokio::select! {
+                note = notes.try_next() => {
+                    let Some(note) = note
+                        .inspect_err(log_status)
+                        .context("Server yielded error status")?
+                    else {
+                        break;
+                    };
+                    self.print('<', &note)?;
+                    self.history.push(Entry::Received(note));
+                }
+                line = lines.next_line(), if tx.is_some() => {
+                    let line = line.context("Failed to read input")?;
+                    let action = match line {
+                        Some(line) => self.command(&line)?,
+                        None => Action::Quit,
+                    };
+                    match action {
+                        Action::Nothing => {}
+                        Action::Send(note) => {
+                            let sender = tx.as_ref().expect("input is only read while sending");
+                            sender.send(note.clone()).await.context("Chat closed")?;
+                            self.history.push(Entry::Sent(note));
+                        }
+                        // Ending the request stream ends the chat, once the server has
+                        // sent the notes in flight.
+                        Action::Quit => tx = None,
+                    }
+                    self.writer.flush()?;
+                }
+            }
+        }
+        writeln!(self.writer, "Chat closed")?;
+        Ok(())
+    }
+
+    /// Runs a command, or makes a note of a message.
+    fn command(&mut self, line: &str) -> anyhow::Result<Action> {
+        let line = line.trim();
+        let Some(command) = line.strip_prefix('/') else {
+            if line.is_empty() {
+                return Ok(Action::Nothing);
+            }
+            let Some(location) = self.location else {
+                writeln!(self.writer, "No location, set one with /at LAT,LON")?;
+                return Ok(Action::Nothing);
+            };
+            return Ok(Action::Send(RouteNote {
+                location: Some(location),
+                message: line.to_string(),
+            }));
+        };
+        let (name, args) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        match name {
+            "at" | "move" if args.trim().is_empty() => match self.location {
+                Some(location) => {
+                    writeln!(self.writer, "At {}", format_point(&location, self.unit))?
+                }
+                None => writeln!(self.writer, "No location, set one with /at LAT,LON")?,
+            },
+            "at" | "move" => match args.parse::<Coordinates>().and_then(|c| c.point(self.unit)) {
+                Ok(location) => {
+                    self.location = Some(location);
+                    self.command("/at")?;
+                }
+                Err(e) => writeln!(self.writer, "{e:#}")?,
+            },
+            "history" => {
+                let history = std::mem::take(&mut self.history);
+                for entry in &history {
+                    match entry {
+                        Entry::Sent(note) => self.print('>', note)?,
+                        Entry::Received(note) => self.print('<', note)?,
+                    }
+                }
+                self.history = history;
+            }
+            "help" => writeln!(self.writer, "{HELP}")?,
+            "quit" | "exit" => return Ok(Action::Quit),
+            _ => writeln!(self.writer, "Unknown command /{name}, /help for commands")?,
+        }
+        Ok(Action::Nothing)
+    }
+
+    fn print(&mut self, direction: char, note: &RouteNote) -> anyhow::Result<()> {
+        writeln!(self.writer, "{direction} {}", note.text(self.unit))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -1,15 +1,107 @@
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
 
 use anyhow::Context;
 use futures::stream::BoxStream;
-use tokio::{fs::File, io, sync::RwLock};
-use tonic::{Request, Response, Status, Streaming};
+use grpc_util::{
+    auth::Policy,
+    compression::Compression,
+    errors::{ErrorDetails, FieldViolation, StatusExt},
+    shutdown::Shutdown,
+    transport::MessageSize,
+};
+use tokio::{
+    fs::File,
+    io,
+    sync::{broadcast, RwLock},
+};
+use tonic::{server::NamedService, Code, Request, Response, Status, Streaming};
+use tonic_health::{server::HealthReporter, ServingStatus};
 
-use crate::route_guide_server::{RouteGuide, RouteGuideServer};
+use crate::{
+    metrics,
+    route_guide_server::{RouteGuide, RouteGuideServer},
+};
 
-#[derive(Debug, Clone, Default)]
+/// Full name of the `RouteGuide` gRPC service, as used by health checks.
+pub const SERVICE_NAME: &str = <RouteGuideServer<RouteGuideService> as NamedService>::NAME;
+
+/// Full names of the `RouteGuide` methods, as matched by authorization policies.
+pub mod methods {
+    pub const GET_FEATURE: &str = "route_guide.RouteGuide/GetFeature";
+    pub const LIST_FEATURES: &str = "route_guide.RouteGuide/ListFeatures";
+    pub const RECORD_ROUTE: &str = "route_guide.RouteGuide/RecordRoute";
+    pub const ROUTE_CHAT: &str = "route_guide.RouteGuide/RouteChat";
+}
+
+/// Reasons of the `ErrorInfo` of `RouteGuide` errors, in the [`SERVICE_NAME`] domain.
+pub mod reasons {
+    /// No feature at the requested point, see the `ResourceInfo`.
+    pub const FEATURE_NOT_FOUND: &str = "FEATURE_NOT_FOUND";
+    /// Missing or out of range coordinates, see the `BadRequest` field violations.
+    pub const INVALID_LOCATION: &str = "INVALID_LOCATION";
+    /// The chat ended before a note could be sent back.
+    pub const CHAT_CLOSED: &str = "CHAT_CLOSED";
+}
+
+/// Latitudes, in E7 degrees, range from -90 to 90 degrees.
+const MAX_LATITUDE: i32 = 900_000_000;
+/// Longitudes, in E7 degrees, range from -180 to 180 degrees.
+const MAX_LONGITUDE: i32 = 1_800_000_000;
+
+/// Authorization policy used unless `AUTH_POLICY` is set.
+///
+/// `read` callers may look features up, `write` callers may also record routes and
+/// chat, and `admin` callers may call anything, including server reflection. Health
+/// checks are public.
+pub fn default_policy() -> Policy {
+    Policy::new()
+        .public("grpc.health.v1.Health/*")
+        .allow("read", methods::GET_FEATURE)
+        .allow("read", methods::LIST_FEATURES)
+        .allow("write", format!("{SERVICE_NAME}/*"))
+        .allow("admin", "*")
+}
+
+/// Number of added features buffered for each [`RouteGuideService::feature_updates`]
+/// receiver.
+const FEATURE_UPDATES_CAPACITY: usize = 256;
+
+/// Chat notes kept for each location by default, see
+/// [`RouteGuideService::with_chat_history`].
+pub const DEFAULT_NOTES_PER_LOCATION: usize = 64;
+/// Locations whose chat notes are kept by default.
+pub const DEFAULT_CHAT_LOCATIONS: usize = 10_000;
+
+#[derive(Debug, Clone)]
 pub struct RouteGuideService {
     features: Arc<RwLock<Vec<crate::Feature>>>,
+    notes: Arc<Mutex<ChatNotes>>,
+    updates: broadcast::Sender<crate::Feature>,
+    shutdown: Shutdown,
+    health: Option<HealthReporter>,
+    compression: Compression,
+    message_size: MessageSize,
+}
+
+impl Default for RouteGuideService {
+    fn default() -> Self {
+        let (updates, _) = broadcast::channel(FEATURE_UPDATES_CAPACITY);
+        Self {
+            features: Default::default(),
+            notes: Arc::new(Mutex::new(ChatNotes::new(
+                DEFAULT_NOTES_PER_LOCATION,
+                DEFAULT_CHAT_LOCATIONS,
+            ))),
+            updates,
+            shutdown: Default::default(),
+            health: None,
+            compression: Default::default(),
+            message_size: Default::default(),
+        }
+    }
 }
 
 impl RouteGuideService {
@@ -17,20 +109,84 @@ impl RouteGuideService {
         Default::default()
     }
 
+    /// Ends `ListFeatures` and `RouteChat` streams with `UNAVAILABLE` once `shutdown`
+    /// is triggered.
+    pub fn with_shutdown(self, shutdown: Shutdown) -> Self {
+        Self { shutdown, ..self }
+    }
+
+    /// Reports `NOT_SERVING` to `reporter` while the database is being loaded by a
+    /// [`RuntimeLoader`].
+    pub fn with_health(self, reporter: HealthReporter) -> Self {
+        Self {
+            health: Some(reporter),
+            ..self
+        }
+    }
+
+    /// Sets the `grpc-encoding` compression of the server returned by [`Self::build`],
+    /// with the overrides of `compression` for `route_guide.RouteGuide` if any.
+    pub fn with_compression(self, compression: Compression) -> Self {
+        Self {
+            compression,
+            ..self
+        }
+    }
+
+    /// Keeps the latest `per_location` chat notes of each location, for the
+    /// `locations` most recently written to.
+    pub fn with_chat_history(self, per_location: usize, locations: usize) -> Self {
+        let notes = ChatNotes::new(per_location, locations);
+        Self {
+            notes: Arc::new(Mutex::new(notes)),
+            ..self
+        }
+    }
+
+    /// Sets the message size limits of the server returned by [`Self::build`].
+    pub fn with_message_size(self, message_size: MessageSize) -> Self {
+        Self {
+            message_size,
+            ..self
+        }
+    }
+
     pub fn build(self) -> RouteGuideServer<Self> {
-        RouteGuideServer::new(self)
+        let compression = self.compression.for_service(SERVICE_NAME);
+        let message_size = self.message_size;
+        let server = compression.apply(
+            RouteGuideServer::new(self),
+            RouteGuideServer::send_compressed,
+            RouteGuideServer::accept_compressed,
+        );
+        message_size.apply(
+            server,
+            RouteGuideServer::max_decoding_message_size,
+            RouteGuideServer::max_encoding_message_size,
+        )
     }
 
     #[tracing::instrument]
     pub fn load(path: &str) -> anyhow::Result<Self> {
+        let start = std::time::Instant::now();
         let features = crate::Feature::db_loader()
             .open(path)
-            .with_context(|| format!("Failed to open file {path}"))?
-            .load()
-            .with_context(|| format!("Failed to read file {path}"))?;
+            .with_context(|| format!("Failed to open file {path}"))
+            .and_then(|loader| {
+                loader
+                    .load()
+                    .with_context(|| format!("Failed to read file {path}"))
+            });
+        metrics::record_load(&features, start.elapsed());
+        let features = features?;
         tracing::info!("Read features");
+        metrics::LOADED_FEATURES.inc_by(features.len() as u64);
+        metrics::FEATURES.set(features.len() as i64);
         let features = Arc::new(RwLock::new(features));
-        Ok(Self { features })
+        Ok(Self {
+            features,
+            ..Default::default()
+        })
     }
 
     pub fn runtime_loader(&self) -> RuntimeLoader<'_> {
@@ -40,6 +196,21 @@ impl RouteGuideService {
         }
     }
 
+    pub(crate) fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
+    /// Receives every feature added by a [`RuntimeLoader`] from now on.
+    pub(crate) fn feature_updates(&self) -> broadcast::Receiver<crate::Feature> {
+        self.updates.subscribe()
+    }
+
+    async fn set_serving_status(&self, status: ServingStatus) {
+        if let Some(mut reporter) = self.health.clone() {
+            reporter.set_service_status(SERVICE_NAME, status).await;
+        }
+    }
+
     async fn find_feature_at(&self, location: &crate::Point) -> Option<crate::Feature> {
         self.features
             .read()
@@ -50,7 +221,7 @@ impl RouteGuideService {
     }
 
     #[tracing::instrument(skip(self))]
-    fn filter_stream_features<'a, 'b>(
+    pub(crate) fn filter_stream_features<'a, 'b>(
         &'a self,
         in_rect: &'b crate::Rectangle,
     ) -> impl futures::Stream<Item = crate::Feature> + Send + use<'a> {
@@ -83,10 +254,81 @@ impl RouteGuideService {
         futures::stream::select(tx_stream, rx).filter_map(futures::future::ready)
     }
 
-    async fn traverse_points<S, E>(&self, mut points: S) -> Result<crate::RouteSummary, E>
+    /// Records each incoming note and replies with the notes kept at the same
+    /// location, from any chat, oldest first and including the incoming one.
+    ///
+    /// Backs both the `RouteChat` RPC and the WebSocket bridge: every chat of the
+    /// service shares the same notes, within the limits of
+    /// [`Self::with_chat_history`].
+    pub(crate) fn chat<S>(
+        &self,
+        mut notes: S,
+    ) -> impl futures::Stream<Item = Result<crate::RouteNote, Status>> + Send + 'static
+    where
+        S: futures::Stream<Item = Result<crate::RouteNote, Status>> + Unpin + Send + 'static,
+    {
+        use futures::{StreamExt, TryStreamExt};
+        use tokio_stream::wrappers::ReceiverStream;
+
+        let (tx, rx) = tokio::sync::mpsc::channel(2);
+        let chat_notes = self.notes.clone();
+        let tx_future = async move {
+            while let Some(note) = notes.try_next().await? {
+                let Some(location) = note.location else {
+                    continue;
+                };
+                check_location(point_violations(&location, "location."))?;
+                let matches = chat_notes.lock().unwrap().record(location, note);
+                for n in matches {
+                    tx.send(Ok(Some(Arc::unwrap_or_clone(n))))
+                        .await
+                        .map_err(|e| {
+                            tracing::error!(error = &e as &dyn std::error::Error, "Send failed");
+                            let details = ErrorDetails::with_error_info(
+                                reasons::CHAT_CLOSED,
+                                SERVICE_NAME,
+                                HashMap::new(),
+                            );
+                            // The client went away.
+                            Status::with_error_details(
+                                Code::Cancelled,
+                                "Failed to send note",
+                                details,
+                            )
+                        })?;
+                }
+            }
+            tracing::debug!("Done route chat");
+            Ok(None)
+        };
+        let tx_stream = futures::stream::once(tx_future);
+        let rx_stream = ReceiverStream::new(rx);
+        let stream = futures::stream::select(tx_stream, rx_stream)
+            .filter_map(|r| async move { r.transpose() });
+        self.shutdown.guard_stream(stream)
+    }
+
+    /// Handles `RecordRoute` for points streamed by any transport, e.g. the REST
+    /// gateway.
+    #[tracing::instrument(name = "record_route", skip_all, fields(peer, caller))]
+    pub(crate) async fn record_points<S>(
+        &self,
+        request: Request<S>,
+    ) -> Result<Response<crate::RouteSummary>, Status>
+    where
+        S: futures::Stream<Item = Result<crate::Point, Status>> + Unpin + Send,
+    {
+        tracing::debug!("Record route");
+        let (_, extensions, points) = request.into_parts();
+        record_identity(&extensions);
+        let summary = self.traverse_points(points).await?;
+        tracing::debug!(?summary, "Done recording");
+        Ok(Response::new(summary))
+    }
+
+    async fn traverse_points<S>(&self, mut points: S) -> Result<crate::RouteSummary, Status>
     where
-        S: futures::Stream<Item = Result<crate::Point, E>> + Unpin + Send,
-        E: Send + Sync + 'static,
+        S: futures::Stream<Item = Result<crate::Point, Status>> + Unpin + Send,
     {
         use futures::TryStreamExt;
         use std::time::Instant;
@@ -97,6 +339,7 @@ impl RouteGuideService {
         let mut distance = 0.0;
         let start = Instant::now();
         while let Some(point) = points.try_next().await? {
+            check_location(point_violations(&point, ""))?;
             count += 1;
             if let Some(lp) = last_point.replace(point) {
                 distance += lp.distance_between(&point);
@@ -116,25 +359,160 @@ impl RouteGuideService {
     }
 }
 
+// MARK: Chat
+
+/// Notes of every chat, keeping the latest ones of each location, and forgetting
+/// the locations least recently written to beyond a limit.
+#[derive(Debug)]
+struct ChatNotes {
+    per_location: usize,
+    max_locations: usize,
+    locations: HashMap<crate::Point, LocationNotes>,
+    /// Locations by their last write, oldest first.
+    by_age: BTreeMap<u64, crate::Point>,
+    writes: u64,
+}
+
+#[derive(Debug, Default)]
+struct LocationNotes {
+    notes: VecDeque<Arc<crate::RouteNote>>,
+    last_write: u64,
+}
+
+impl ChatNotes {
+    fn new(per_location: usize, max_locations: usize) -> Self {
+        Self {
+            per_location: per_location.max(1),
+            max_locations: max_locations.max(1),
+            locations: HashMap::new(),
+            by_age: BTreeMap::new(),
+            writes: 0,
+        }
+    }
+
+    /// Records `note` at `location`, returning the notes kept there, oldest first.
+    ///
+    /// Only shares the notes, at most `per_location` of them, so that they are copied
+    /// once the lock is released.
+    fn record(
+        &mut self,
+        location: crate::Point,
+        note: crate::RouteNote,
+    ) -> Vec<Arc<crate::RouteNote>> {
+        self.writes += 1;
+        let entry = self.locations.entry(location).or_default();
+        self.by_age.remove(&entry.last_write);
+        entry.last_write = self.writes;
+        self.by_age.insert(self.writes, location);
+        if entry.notes.len() == self.per_location {
+            entry.notes.pop_front();
+        }
+        entry.notes.push_back(Arc::new(note));
+        let notes = entry.notes.iter().cloned().collect();
+        while self.locations.len() > self.max_locations {
+            let Some((_, oldest)) = self.by_age.pop_first() else {
+                break;
+            };
+            self.locations.remove(&oldest);
+        }
+        notes
+    }
+}
+
+// MARK: Errors
+
+/// Violations of the coordinate ranges by `point`, named `field`.
+fn point_violations(point: &crate::Point, field: &str) -> Vec<FieldViolation> {
+    let mut violations = Vec::new();
+    if !(-MAX_LATITUDE..=MAX_LATITUDE).contains(&point.latitude) {
+        violations.push(FieldViolation::new(
+            format!("{field}latitude"),
+            "must be between -90 and 90 degrees (E7)",
+        ));
+    }
+    if !(-MAX_LONGITUDE..=MAX_LONGITUDE).contains(&point.longitude) {
+        violations.push(FieldViolation::new(
+            format!("{field}longitude"),
+            "must be between -180 and 180 degrees (E7)",
+        ));
+    }
+    violations
+}
+
+/// Violations of the coordinate ranges by the corners of `rect`, which are required.
+fn rectangle_violations(rect: &crate::Rectangle) -> Vec<FieldViolation> {
+    [("lo", &rect.lo), ("hi", &rect.hi)]
+        .into_iter()
+        .flat_map(|(field, point)| match point {
+            Some(point) => point_violations(point, &format!("{field}.")),
+            None => vec![FieldViolation::new(field, "is required")],
+        })
+        .collect()
+}
+
+fn check_location(violations: Vec<FieldViolation>) -> Result<(), Status> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let mut details = ErrorDetails::with_bad_request(violations);
+    details.set_error_info(reasons::INVALID_LOCATION, SERVICE_NAME, HashMap::new());
+    Err(Status::with_error_details(
+        Code::InvalidArgument,
+        "Invalid location",
+        details,
+    ))
+}
+
+fn feature_not_found(point: &crate::Point) -> Status {
+    let name = format!("{},{}", point.latitude, point.longitude);
+    let mut details = ErrorDetails::with_resource_info(
+        "route_guide.Feature",
+        &name,
+        "",
+        "No feature at this location",
+    );
+    let metadata = HashMap::from([
+        ("latitude".to_string(), point.latitude.to_string()),
+        ("longitude".to_string(), point.longitude.to_string()),
+    ]);
+    details.set_error_info(reasons::FEATURE_NOT_FOUND, SERVICE_NAME, metadata);
+    Status::with_error_details(Code::NotFound, "No feature found", details)
+}
+
+/// Records the verified TLS client identity and the authenticated caller, if any, on
+/// the current span.
+fn record_identity(extensions: &tonic::Extensions) {
+    let span = tracing::Span::current();
+    if let Some(peer) = extensions.get::<grpc_util::tls::PeerIdentity>() {
+        span.record("peer", tracing::field::display(peer));
+    }
+    if let Some(caller) = extensions.get::<grpc_util::auth::Caller>() {
+        span.record("caller", tracing::field::display(caller));
+    }
+}
+
 #[tonic::async_trait]
 impl RouteGuide for RouteGuideService {
-    #[tracing::instrument(skip(self))]
+    // The metadata may carry a bearer token, so only the message is recorded.
+    #[tracing::instrument(skip_all, fields(request = ?request.get_ref(), peer, caller))]
     async fn get_feature(
         &self,
         request: Request<crate::Point>,
     ) -> Result<Response<crate::Feature>, Status> {
         tracing::debug!("Get features");
-        let (_, _, request) = request.into_parts();
+        let (_, extensions, request) = request.into_parts();
+        record_identity(&extensions);
+        check_location(point_violations(&request, ""))?;
         let Some(response) = self.find_feature_at(&request).await else {
             tracing::info!("No feature found");
-            return Err(Status::not_found("No feature found"));
+            return Err(feature_not_found(&request));
         };
         Ok(Response::new(response.clone()))
     }
 
     type ListFeaturesStream = BoxStream<'static, Result<crate::Feature, Status>>;
 
-    #[tracing::instrument(skip(self))]
+    #[tracing::instrument(skip_all, fields(request = ?request.get_ref(), peer, caller))]
     async fn list_features(
         &self,
         request: Request<crate::Rectangle>,
@@ -142,64 +520,39 @@ impl RouteGuide for RouteGuideService {
         use futures::StreamExt;
 
         tracing::debug!("List features");
-        let (_, _, request) = request.into_parts();
+        let (_, extensions, request) = request.into_parts();
+        record_identity(&extensions);
+        check_location(rectangle_violations(&request))?;
         let s = self.clone();
         let stream = async_stream::stream! {
             for await f in s.filter_stream_features(&request) {
                 yield Ok(f);
             }
         };
+        let stream = self.shutdown.guard_stream(stream);
         Ok(Response::new(stream.boxed()))
     }
 
-    #[tracing::instrument(skip_all)]
     async fn record_route(
         &self,
         request: Request<Streaming<crate::Point>>,
     ) -> Result<Response<crate::RouteSummary>, Status> {
-        tracing::debug!("Record route");
-        let (_, _, points) = request.into_parts();
-        let summary = self.traverse_points(points).await?;
-        tracing::debug!(?summary, "Done recording");
-        Ok(Response::new(summary))
+        self.record_points(request).await
     }
 
     type RouteChatStream = BoxStream<'static, Result<crate::RouteNote, Status>>;
 
-    #[tracing::instrument(skip_all)]
+    #[tracing::instrument(skip_all, fields(peer, caller))]
     async fn route_chat(
         &self,
         request: Request<Streaming<crate::RouteNote>>,
     ) -> Result<Response<Self::RouteChatStream>, Status> {
-        use futures::{StreamExt, TryStreamExt};
-        use std::collections::HashMap;
-        use tokio_stream::wrappers::ReceiverStream;
+        use futures::StreamExt;
 
         tracing::debug!("Route chat");
-        let (_, _, mut notes) = request.into_parts();
-        let (tx, rx) = tokio::sync::mpsc::channel(2);
-        let tx_future = async move {
-            let mut read_notes: HashMap<_, Vec<_>> = HashMap::new();
-            while let Some(note) = notes.try_next().await? {
-                let Some(location) = note.location else {
-                    continue;
-                };
-                let matches = read_notes.entry(location).or_default();
-                matches.push(note);
-                for n in matches {
-                    tx.send(Ok(Some(n.clone()))).await.map_err(|e| {
-                        tracing::error!(error = &e as &dyn std::error::Error, "Send failed");
-                        Status::internal("")
-                    })?;
-                }
-            }
-            tracing::debug!("Done route chat");
-            Ok(None)
-        };
-        let tx_stream = futures::stream::once(tx_future);
-        let rx_stream = ReceiverStream::new(rx);
-        let stream = futures::stream::select(tx_stream, rx_stream)
-            .filter_map(|r| async move { r.transpose() });
+        let (_, extensions, notes) = request.into_parts();
+        record_identity(&extensions);
+        let stream = self.chat(notes);
         Ok(Response::new(stream.boxed()))
     }
 }
@@ -233,6 +586,25 @@ impl<'a, R> RuntimeLoader<'a, R> {
     }
 
     pub async fn load(self) -> anyhow::Result<()>
+    where
+        R: io::AsyncRead + Unpin,
+    {
+        let service = self.service;
+        service.set_serving_status(ServingStatus::NotServing).await;
+        let start = std::time::Instant::now();
+        let res = self.load_features().await;
+        metrics::record_load(&res, start.elapsed());
+        // Features loaded before a failed reload are still served.
+        let serving = res.is_ok() || !service.features.read().await.is_empty();
+        if serving && !service.shutdown.is_triggered() {
+            service.set_serving_status(ServingStatus::Serving).await;
+        }
+        res?;
+        tracing::info!("Loaded features");
+        Ok(())
+    }
+
+    async fn load_features(self) -> anyhow::Result<()>
     where
         R: io::AsyncRead + Unpin,
     {
@@ -253,7 +625,13 @@ impl<'a, R> RuntimeLoader<'a, R> {
         let mut new_features: Vec<crate::Feature> =
             serde_json::from_str(&buf).context("Failed to parse features JSON")?;
         let mut features = service.features.write().await;
+        for feature in &new_features {
+            // fails only when nobody is subscribed
+            let _ = service.updates.send(feature.clone());
+        }
+        metrics::LOADED_FEATURES.inc_by(new_features.len() as u64);
         features.append(&mut new_features);
+        metrics::FEATURES.set(features.len() as i64);
         Ok(())
     }
 }
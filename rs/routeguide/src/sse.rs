@@ -0,0 +1,96 @@
+//! Server-sent events at `GET /v1/features/events?lo_lat=&lo_lon=&hi_lat=&hi_lon=`.
+//!
+//! Sends a `feature` event for every feature in the rectangle, as `ListFeatures`
+//! would, then a `synced` event. Features later added to the rectangle follow as
+//! further `feature` events. Errors, including shutdown, end the stream with an
+//! `error` event.
+//!
+//! A client too slow to keep up with the features added gets a `resync` event, after
+//! which every feature in the rectangle is sent again, followed by `synced`.
+
+use std::{collections::HashSet, convert::Infallible};
+
+use axum::{
+    extract::{Query, State},
+    http::Extensions,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{Stream, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use tonic::Status;
+
+use crate::{
+    gateway::{ErrorBody, FeaturesQuery, GatewayError},
+    server::{methods, RouteGuideService},
+};
+
+pub fn router(service: RouteGuideService) -> axum::Router {
+    axum::Router::new()
+        .route("/v1/features/events", axum::routing::get(feature_events))
+        .with_state(service)
+}
+
+fn json_event(name: &str, data: &impl serde::Serialize) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|e| {
+            tracing::error!(error = &e as &dyn std::error::Error, "Serialize failed");
+            Event::default().event("error")
+        })
+}
+
+#[tracing::instrument(skip(service, extensions))]
+async fn feature_events(
+    State(service): State<RouteGuideService>,
+    Query(query): Query<FeaturesQuery>,
+    extensions: Extensions,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, GatewayError> {
+    grpc_util::auth::authorize(&extensions, methods::LIST_FEATURES)?;
+    let Some(rect) = query.rectangle() else {
+        let status = Status::invalid_argument("lo_lat, lo_lon, hi_lat and hi_lon are required");
+        return Err(status.into());
+    };
+    // Subscribe before listing so that no feature added in between is missed.
+    let mut updates = service.feature_updates();
+    let shutdown = service.shutdown().clone();
+    let events = async_stream::stream! {
+        'sync: loop {
+            let mut listed = HashSet::new();
+            for await f in service.filter_stream_features(&rect) {
+                if let Some(location) = f.location {
+                    listed.insert((f.name.clone(), location));
+                }
+                yield Ok(json_event("feature", &f));
+            }
+            yield Ok(Event::default().event("synced").data("{}"));
+            // Updates received during the listing may already have been listed.
+            let mut queued = updates.len();
+            loop {
+                let update = updates.recv().await;
+                let was_queued = queued > 0;
+                queued = queued.saturating_sub(1);
+                match update {
+                    Ok(f) => {
+                        let Some(location) = f.location else { continue };
+                        let sent = was_queued && listed.contains(&(f.name.clone(), location));
+                        if rect.contains(&location) && !sent {
+                            yield Ok(json_event("feature", &f));
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "Feature updates lagged, resyncing");
+                        yield Ok(Event::default().event("resync").data("{}"));
+                        continue 'sync;
+                    }
+                    Err(RecvError::Closed) => break 'sync,
+                }
+            }
+        }
+    };
+    let events = shutdown.guard_stream(events).map(|event| {
+        let event = event.unwrap_or_else(|status| json_event("error", &ErrorBody::from(&status)));
+        Ok(event)
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
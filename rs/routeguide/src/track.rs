@@ -0,0 +1,240 @@
+//! GPX tracks and GeoJSON `LineString`s, replayed into `RecordRoute`.
+
+use std::{path::Path, time::Duration};
+
+use anyhow::Context;
+use serde_json::Value;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{
+    coordinates::{Coordinates, Unit},
+    Point,
+};
+
+/// Point of a track, with its timestamp when the track has some.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPoint {
+    pub point: Point,
+    pub time: Option<OffsetDateTime>,
+}
+
+impl From<Point> for TrackPoint {
+    fn from(point: Point) -> Self {
+        Self { point, time: None }
+    }
+}
+
+fn degrees(latitude: f64, longitude: f64) -> anyhow::Result<Point> {
+    Coordinates {
+        latitude,
+        longitude,
+    }
+    .point(Unit::Degrees)
+}
+
+fn parse_time(s: &str) -> anyhow::Result<OffsetDateTime> {
+    OffsetDateTime::parse(s.trim(), &Rfc3339).with_context(|| format!("Invalid timestamp {s}"))
+}
+
+/// Reads a GPX (`.gpx`) or GeoJSON (`.geojson`, `.json`) track, telling them apart
+/// by their first character for other extensions.
+pub fn load(path: &Path) -> anyhow::Result<Vec<TrackPoint>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let extension = path.extension().and_then(|e| e.to_str());
+    let gpx = match extension {
+        Some(e) if e.eq_ignore_ascii_case("gpx") => true,
+        Some(e) if e.eq_ignore_ascii_case("geojson") || e.eq_ignore_ascii_case("json") => false,
+        _ => text.trim_start().starts_with('<'),
+    };
+    let track = if gpx {
+        parse_gpx(&text)
+    } else {
+        parse_geojson(&text)
+    };
+    let track = track.with_context(|| format!("Failed to parse {}", path.display()))?;
+    anyhow::ensure!(!track.is_empty(), "No point in {}", path.display());
+    Ok(track)
+}
+
+// MARK: GPX
+
+/// Points of the tracks (`trkpt`) of a GPX document, in order, or of its routes
+/// (`rtept`) when it has no track. Timestamps are read from `time` elements.
+pub fn parse_gpx(text: &str) -> anyhow::Result<Vec<TrackPoint>> {
+    let doc = roxmltree::Document::parse(text).context("Invalid XML")?;
+    anyhow::ensure!(
+        doc.root_element().has_tag_name("gpx"),
+        "Expected a gpx document, got {}",
+        doc.root_element().tag_name().name()
+    );
+    let points = |tag: &str| {
+        doc.descendants()
+            .filter(|n| n.has_tag_name(tag))
+            .map(|n| {
+                let pos = doc.text_pos_at(n.range().start);
+                gpx_point(n).with_context(|| format!("Invalid {tag} at {pos}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    };
+    let track = points("trkpt")?;
+    if !track.is_empty() {
+        return Ok(track);
+    }
+    points("rtept")
+}
+
+fn gpx_point(node: roxmltree::Node) -> anyhow::Result<TrackPoint> {
+    let coordinate = |name: &str| {
+        let value = node
+            .attribute(name)
+            .with_context(|| format!("Missing {name}"))?;
+        value
+            .parse::<f64>()
+            .with_context(|| format!("Invalid {name} {value}"))
+    };
+    let time = node
+        .children()
+        .find(|n| n.has_tag_name("time"))
+        .and_then(|n| n.text())
+        .map(parse_time)
+        .transpose()?;
+    Ok(TrackPoint {
+        point: degrees(coordinate("lat")?, coordinate("lon")?)?,
+        time,
+    })
+}
+
+// MARK: GeoJSON
+
+/// Points of the `LineString` and `MultiLineString` geometries of a GeoJSON
+/// geometry, feature or feature collection, in order.
+///
+/// Timestamps are read from the `coordTimes` or `coordinateProperties.times`
+/// property of features, with one RFC 3339 timestamp per position, as written by
+/// GPX converters.
+pub fn parse_geojson(text: &str) -> anyhow::Result<Vec<TrackPoint>> {
+    let value: Value = serde_json::from_str(text).context("Invalid JSON")?;
+    let mut track = Vec::new();
+    geojson_object(&value, None, &mut track)?;
+    anyhow::ensure!(!track.is_empty(), "No LineString geometry");
+    Ok(track)
+}
+
+fn geojson_object(
+    value: &Value,
+    times: Option<&Value>,
+    track: &mut Vec<TrackPoint>,
+) -> anyhow::Result<()> {
+    let kind = value["type"].as_str().context("Missing type")?;
+    match kind {
+        "FeatureCollection" => {
+            let features = value["features"].as_array().context("Missing features")?;
+            for (i, feature) in features.iter().enumerate() {
+                geojson_object(feature, None, track).with_context(|| format!("In feature {i}"))?;
+            }
+        }
+        "Feature" => {
+            let properties = &value["properties"];
+            let times = [
+                &properties["coordTimes"],
+                &properties["coordinateProperties"]["times"],
+            ]
+            .into_iter()
+            .find(|t| !t.is_null());
+            if !value["geometry"].is_null() {
+                geojson_object(&value["geometry"], times, track)?;
+            }
+        }
+        "GeometryCollection" => {
+            let geometries = value["geometries"]
+                .as_array()
+                .context("Missing geometries")?;
+            for geometry in geometries {
+                geojson_object(geometry, None, track)?;
+            }
+        }
+        "LineString" => line(&value["coordinates"], times, track)?,
+        "MultiLineString" => {
+            let lines = value["coordinates"]
+                .as_array()
+                .context("Missing coordinates")?;
+            for (i, coordinates) in lines.iter().enumerate() {
+                line(coordinates, times.map(|t| &t[i]), track)
+                    .with_context(|| format!("In line {i}"))?;
+            }
+        }
+        // Points and polygons aren't routes.
+        _ => {}
+    }
+    Ok(())
+}
+
+fn line(
+    coordinates: &Value,
+    times: Option<&Value>,
+    track: &mut Vec<TrackPoint>,
+) -> anyhow::Result<()> {
+    let positions = coordinates.as_array().context("Missing coordinates")?;
+    let times = match times {
+        Some(times) => {
+            let times = times.as_array().context("Timestamps aren't an array")?;
+            anyhow::ensure!(
+                times.len() == positions.len(),
+                "{} timestamps for {} positions",
+                times.len(),
+                positions.len()
+            );
+            times
+                .iter()
+                .map(|t| parse_time(t.as_str().context("Timestamps aren't strings")?).map(Some))
+                .collect::<anyhow::Result<_>>()?
+        }
+        None => vec![None; positions.len()],
+    };
+    for (i, (position, time)) in positions.iter().zip(times).enumerate() {
+        // GeoJSON positions are [longitude, latitude, elevation?].
+        let (Some(longitude), Some(latitude)) = (position[0].as_f64(), position[1].as_f64()) else {
+            anyhow::bail!("Invalid position {i}: {position}");
+        };
+        let point =
+            degrees(latitude, longitude).with_context(|| format!("Invalid position {i}"))?;
+        track.push(TrackPoint { point, time });
+    }
+    Ok(())
+}
+
+// MARK: Replay
+
+/// Streams the points of `track`, all at once, or when `speed` is set, at `speed`
+/// times the pace of their timestamps. Points without a timestamp follow the
+/// previous one immediately, as do points timestamped before it.
+///
+/// The pace starts when the first point is polled, not when the stream is built,
+/// so that connecting the call doesn't delay the points after it. Points due later
+/// than the clock can represent, e.g. at tiny speeds, are never sent.
+pub fn replay(
+    track: Vec<TrackPoint>,
+    speed: Option<f64>,
+) -> impl futures::Stream<Item = Point> + Send + 'static {
+    let first = track.iter().find_map(|p| p.time);
+    futures::stream::unfold(
+        (track.into_iter(), None),
+        move |(mut points, start)| async move {
+            let p = points.next()?;
+            let start = start.unwrap_or_else(tokio::time::Instant::now);
+            if let (Some(speed), Some(first), Some(time)) = (speed, first, p.time) {
+                if let Ok(offset) = Duration::try_from(time - first) {
+                    let deadline = Duration::try_from_secs_f64(offset.as_secs_f64() / speed)
+                        .ok()
+                        .and_then(|delay| start.checked_add(delay));
+                    match deadline {
+                        Some(deadline) => tokio::time::sleep_until(deadline).await,
+                        None => std::future::pending().await,
+                    }
+                }
+            }
+            Some((p.point, (points, Some(start))))
+        },
+    )
+}
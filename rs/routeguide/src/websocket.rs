@@ -0,0 +1,106 @@
+//! WebSocket bridge for `RouteChat` at `GET /v1/chat`.
+//!
+//! Each text (or binary) frame carries one [`RouteNote`](crate::RouteNote) as JSON in
+//! either direction. The chat is backed by the same notes as the gRPC `RouteChat`
+//! RPC, so browsers and native clients can talk to each other.
+
+use axum::{
+    extract::{
+        ws::{CloseFrame, Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    http::Extensions,
+    response::Response,
+};
+use futures::{SinkExt, StreamExt};
+use tonic::{Code, Status};
+
+use crate::{
+    gateway::GatewayError,
+    server::{methods, RouteGuideService},
+};
+
+pub fn router(service: RouteGuideService) -> axum::Router {
+    axum::Router::new()
+        .route("/v1/chat", axum::routing::get(chat))
+        .with_state(service)
+}
+
+async fn chat(
+    ws: WebSocketUpgrade,
+    State(service): State<RouteGuideService>,
+    extensions: Extensions,
+) -> Result<Response, GatewayError> {
+    grpc_util::auth::authorize(&extensions, methods::ROUTE_CHAT)?;
+    Ok(ws.on_upgrade(move |socket| bridge(service, socket)))
+}
+
+fn parse_note(message: Result<Message, axum::Error>) -> Option<Result<crate::RouteNote, Status>> {
+    let note = match message {
+        Ok(Message::Text(text)) => serde_json::from_str(&text),
+        Ok(Message::Binary(bytes)) => serde_json::from_slice(&bytes),
+        Ok(Message::Ping(_) | Message::Pong(_) | Message::Close(_)) => return None,
+        Err(e) => {
+            tracing::debug!(error = &e as &dyn std::error::Error, "WebSocket error");
+            return Some(Err(Status::cancelled("WebSocket closed")));
+        }
+    };
+    let note = note.map_err(|e| Status::invalid_argument(format!("Invalid RouteNote: {e}")));
+    Some(note)
+}
+
+/// Close frame for a chat that ended with `status`.
+fn close_frame(status: &Status) -> CloseFrame {
+    use axum::extract::ws::close_code;
+
+    let code = match status.code() {
+        Code::InvalidArgument => close_code::INVALID,
+        Code::Unavailable => close_code::RESTART,
+        _ => close_code::ERROR,
+    };
+    // Close reasons are limited to 123 bytes.
+    let message = status.message();
+    let mut end = message.len().min(123);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    CloseFrame {
+        code,
+        reason: message[..end].into(),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn bridge(service: RouteGuideService, socket: WebSocket) {
+    tracing::debug!("WebSocket chat");
+    let (mut sink, stream) = socket.split();
+    let notes = stream.filter_map(|m| futures::future::ready(parse_note(m)));
+    let replies = service.chat(Box::pin(notes));
+    futures::pin_mut!(replies);
+    while let Some(reply) = replies.next().await {
+        let message = match reply {
+            Ok(note) => match serde_json::to_string(&note) {
+                Ok(json) => Message::Text(json.into()),
+                Err(e) => {
+                    tracing::error!(error = &e as &dyn std::error::Error, "Serialize failed");
+                    Message::Close(Some(close_frame(&Status::internal(""))))
+                }
+            },
+            Err(status) if status.code() == Code::Cancelled => break,
+            Err(status) => Message::Close(Some(close_frame(&status))),
+        };
+        let closing = matches!(message, Message::Close(_));
+        if let Err(e) = sink.send(message).await {
+            tracing::debug!(
+                error = &e as &dyn std::error::Error,
+                "WebSocket send failed"
+            );
+            return;
+        }
+        if closing {
+            return;
+        }
+    }
+    let _ = sink.close().await;
+    tracing::debug!("Done WebSocket chat");
+}
@@ -0,0 +1,72 @@
+//! Combinations of the workspace services on one port.
+
+use grpc_util::{bootstrap::Bootstrap, serve::Server};
+use http_body_util::BodyExt;
+use routeguide::{all_in_one::Services, route_guide_client::RouteGuideClient, Point};
+use tonic::Code;
+use tower::ServiceExt;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+#[test]
+fn parses_services() -> anyhow::Result<()> {
+    let services: Services = "greeter, http,".parse()?;
+    assert_eq!(
+        services,
+        Services {
+            greeter: true,
+            http: true,
+            ..Default::default()
+        }
+    );
+    assert_eq!("".parse::<Services>()?, Services::default());
+    let err = "greeter,nope".parse::<Services>().unwrap_err();
+    assert_eq!(err.to_string(), "Unknown service nope in SERVICES");
+    Ok(())
+}
+
+#[tokio::test]
+async fn serves_http_endpoints_without_the_grpc_service() -> anyhow::Result<()> {
+    let services = Services {
+        http: true,
+        ..Default::default()
+    };
+    let (bootstrap, route_guide) = services.apply(Bootstrap::new()).await;
+    let route_guide = route_guide.expect("a RouteGuide service for the HTTP endpoints");
+    route_guide
+        .runtime_loader()
+        .open(DB_PATH)
+        .await?
+        .load()
+        .await?;
+    let shutdown = bootstrap.shutdown().clone();
+    let (server, connector) = Server::in_memory();
+    tokio::spawn(bootstrap.serve_on(server));
+    let channel = connector.channel().await?;
+
+    let uri = "http://localhost/v1/features?lat=409146138&lon=-746188906";
+    let request = http::Request::get(uri).body(tonic::body::empty_body())?;
+    let response = channel.clone().oneshot(request).await?;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    let body = response.into_body().collect().await?.to_bytes();
+    let feature: serde_json::Value = serde_json::from_slice(&body)?;
+    assert_eq!(
+        feature["name"],
+        "Berkshire Valley Management Area Trail, Jefferson, NJ, USA"
+    );
+
+    let point = Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    let status = RouteGuideClient::new(channel)
+        .get_feature(point)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unimplemented, "{status:?}");
+    shutdown.trigger();
+    Ok(())
+}
@@ -0,0 +1,267 @@
+//! Authentication and authorization of calls, whatever their content type.
+
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use grpc_util::{
+    auth::{Authenticator, Caller},
+    bootstrap::Bootstrap,
+    errors::reasons,
+};
+use http_body_util::BodyExt;
+use jsonwebtoken::Algorithm;
+use routeguide::{embedded::EmbeddedServer, route_guide_client::RouteGuideClient, Point};
+use tonic::{service::interceptor::InterceptedService, Code, Request};
+use tower::ServiceExt;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+fn authenticator() -> Authenticator {
+    Authenticator::new()
+        .api_key("reader", "dashboard", ["read"])
+        .api_key("writer", "tracker", ["write"])
+        .api_key("operator", "prometheus", ["admin"])
+}
+
+/// Serves `RouteGuide`, and `/ping` and `/assets.v1/app.js` on the HTTP side.
+async fn start() -> anyhow::Result<EmbeddedServer> {
+    let ping = axum::Router::new()
+        .route("/ping", axum::routing::post(|| async { "pong" }))
+        .route("/assets.v1/app.js", axum::routing::post(|| async { "app" }));
+    let bootstrap = Bootstrap::new()
+        .authenticator(Some(authenticator()))
+        .http(ping);
+    EmbeddedServer::start_in_memory(bootstrap, DB_PATH).await
+}
+
+/// Sends an empty `POST` to `path`, returning the HTTP status and the `grpc-status`
+/// of the response headers or trailers, if any.
+async fn send(
+    server: &EmbeddedServer,
+    path: &str,
+    content_type: Option<&str>,
+    token: Option<&str>,
+) -> anyhow::Result<(http::StatusCode, Option<Code>)> {
+    let mut request = http::Request::post(format!("http://localhost{path}"));
+    if let Some(content_type) = content_type {
+        request = request.header(http::header::CONTENT_TYPE, content_type);
+    }
+    if let Some(token) = token {
+        request = request.header(http::header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let request = request.body(tonic::body::empty_body())?;
+    let (parts, body) = server.channel().oneshot(request).await?.into_parts();
+    let trailers = body.collect().await?.trailers().cloned();
+    let code = [Some(&parts.headers), trailers.as_ref()]
+        .into_iter()
+        .flatten()
+        .find_map(|headers| headers.get("grpc-status"))
+        .map(|v| Code::from_bytes(v.as_bytes()));
+    Ok((parts.status, code))
+}
+
+async fn post(
+    server: &EmbeddedServer,
+    path: &str,
+    content_type: Option<&str>,
+    token: Option<&str>,
+) -> anyhow::Result<Option<Code>> {
+    Ok(send(server, path, content_type, token).await?.1)
+}
+
+#[tokio::test]
+async fn checks_calls_whatever_their_content_type() -> anyhow::Result<()> {
+    let server = start().await?;
+    let path = "/route_guide.RouteGuide/RecordRoute";
+    for content_type in [Some("text/plain"), Some("application/json"), None] {
+        let code = post(&server, path, content_type, None).await?;
+        assert_eq!(code, Some(Code::Unauthenticated), "{content_type:?}");
+        let code = post(&server, path, content_type, Some("reader")).await?;
+        assert_eq!(code, Some(Code::PermissionDenied), "{content_type:?}");
+    }
+    // Reaches the handler, which records an empty route.
+    let code = post(&server, path, Some("application/grpc"), Some("writer")).await?;
+    assert_eq!(code, Some(Code::Ok));
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn routes_method_paths_to_grpc_services() -> anyhow::Result<()> {
+    let server = start().await?;
+    // Not gRPC, but shaped like a method, hence checked as one.
+    let path = "/route_guide.RouteGuide/GetFeature";
+    let code = post(&server, path, Some("application/json"), None).await?;
+    assert_eq!(code, Some(Code::Unauthenticated));
+    let code = post(&server, path, Some("application/json"), Some("writer")).await?;
+    assert_ne!(code, Some(Code::PermissionDenied));
+    assert!(code.is_some(), "answered by the gRPC side");
+
+    // gRPC calls to unknown services too.
+    let path = "/pkg.Unknown/Method";
+    let code = post(&server, path, Some("application/grpc"), Some("writer")).await?;
+    assert_eq!(code, Some(Code::PermissionDenied));
+
+    // HTTP routes authorize themselves, whatever the shape of their path.
+    let (status, code) = send(&server, "/ping", Some("text/plain"), None).await?;
+    assert_eq!((status, code), (http::StatusCode::OK, None));
+    let (status, code) = send(&server, "/assets.v1/app.js", None, None).await?;
+    assert_eq!((status, code), (http::StatusCode::OK, None));
+    let (status, code) = send(&server, path, None, Some("writer")).await?;
+    assert_eq!((status, code), (http::StatusCode::NOT_FOUND, None));
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn bootstrap_enforces_the_policy() -> anyhow::Result<()> {
+    let server = start().await?;
+    let client = |token: Option<&'static str>| {
+        let channel = InterceptedService::new(server.channel(), move |mut req: Request<()>| {
+            if let Some(token) = token {
+                let value = format!("Bearer {token}").parse().unwrap();
+                req.metadata_mut().insert("authorization", value);
+            }
+            Ok(req)
+        });
+        RouteGuideClient::new(channel)
+    };
+    let point = Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    let route = || futures::stream::iter([point]);
+
+    let status = client(None).get_feature(point).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert_eq!(
+        grpc_util::errors::reason(&status).as_deref(),
+        Some(reasons::MISSING_CREDENTIALS)
+    );
+    let status = client(Some("nope")).get_feature(point).await.unwrap_err();
+    assert_eq!(
+        grpc_util::errors::reason(&status).as_deref(),
+        Some(reasons::INVALID_CREDENTIALS)
+    );
+
+    client(Some("reader")).get_feature(point).await?;
+    let status = client(Some("reader"))
+        .record_route(route())
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let summary = client(Some("writer")).record_route(route()).await?;
+    assert_eq!(summary.into_inner().point_count, 1);
+
+    // Health checks are public.
+    let mut health = tonic_health::pb::health_client::HealthClient::new(server.channel());
+    let request = tonic_health::pb::HealthCheckRequest::default();
+    health.check(request).await?;
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn reserves_metrics_for_admins() -> anyhow::Result<()> {
+    let bootstrap = Bootstrap::new()
+        .authenticator(Some(authenticator()))
+        .admin(true);
+    let server = EmbeddedServer::start_in_memory(bootstrap, DB_PATH).await?;
+    for (token, status) in [
+        (None, http::StatusCode::UNAUTHORIZED),
+        (Some("writer"), http::StatusCode::FORBIDDEN),
+        (Some("operator"), http::StatusCode::OK),
+    ] {
+        let mut request = http::Request::get("http://localhost/metrics");
+        if let Some(token) = token {
+            request = request.header(http::header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let request = request.body(tonic::body::empty_body())?;
+        let response = server.channel().oneshot(request).await?;
+        assert_eq!(response.status(), status, "{token:?}");
+    }
+    server.shutdown().await
+}
+
+/// Signs `claims` with a new ES256 key, returning the token and the path of the PEM
+/// public key.
+fn sign(name: &str, claims: &serde_json::Value) -> anyhow::Result<(String, PathBuf)> {
+    let key = rcgen::KeyPair::generate()?;
+    let path = std::env::temp_dir().join(format!("routeguide-{name}-{}.pem", std::process::id()));
+    std::fs::write(&path, key.public_key_pem())?;
+    let encoding = jsonwebtoken::EncodingKey::from_ec_pem(key.serialize_pem().as_bytes())?;
+    let header = jsonwebtoken::Header::new(Algorithm::ES256);
+    Ok((jsonwebtoken::encode(&header, claims, &encoding)?, path))
+}
+
+fn authenticate(authenticator: &Authenticator, token: &str) -> Result<Caller, tonic::Status> {
+    let mut headers = http::HeaderMap::new();
+    let value = format!("Bearer {token}").parse().unwrap();
+    headers.insert(http::header::AUTHORIZATION, value);
+    Ok(authenticator.authenticate(&headers)?.expect("a caller"))
+}
+
+fn assert_invalid(result: Result<Caller, tonic::Status>) {
+    let status = result.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert_eq!(
+        grpc_util::errors::reason(&status).as_deref(),
+        Some(reasons::INVALID_CREDENTIALS)
+    );
+}
+
+#[test]
+fn verifies_jwts() -> anyhow::Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut claims = serde_json::json!({
+        "sub": "tracker",
+        "exp": now + 600,
+        "iss": "https://issuer.example",
+        "aud": "routeguide",
+        "scope": "read write",
+    });
+    let (token, key) = sign("jwt", &claims)?;
+
+    // Audiences are only checked when expected.
+    let authenticator = Authenticator::new().jwt_public_key(&key, Algorithm::ES256)?;
+    let caller = authenticate(&authenticator, &token)?;
+    assert_eq!(caller.subject(), "tracker");
+    assert!(caller.has_role("read") && caller.has_role("write"));
+
+    // Whatever the order of the builder calls.
+    let authenticator = Authenticator::new()
+        .jwt_issuer("https://issuer.example")
+        .jwt_audience("routeguide")
+        .jwt_public_key(&key, Algorithm::ES256)?;
+    authenticate(&authenticator, &token)?;
+    let authenticator = Authenticator::new()
+        .jwt_issuer("https://other.example")
+        .jwt_public_key(&key, Algorithm::ES256)?;
+    assert_invalid(authenticate(&authenticator, &token));
+    let authenticator = Authenticator::new()
+        .jwt_public_key(&key, Algorithm::ES256)?
+        .jwt_audience("other");
+    assert_invalid(authenticate(&authenticator, &token));
+
+    claims.as_object_mut().unwrap().remove("aud");
+    let (token, key_without_aud) = sign("jwt-without-aud", &claims)?;
+    let authenticator = Authenticator::new().jwt_public_key(&key_without_aud, Algorithm::ES256)?;
+    authenticate(&authenticator, &token)?;
+    assert_invalid(authenticate(
+        &authenticator.jwt_audience("routeguide"),
+        &token,
+    ));
+
+    // Expired beyond the leeway.
+    claims["exp"] = (now - 600).into();
+    let (token, key_expired) = sign("jwt-expired", &claims)?;
+    let authenticator = Authenticator::new().jwt_public_key(&key_expired, Algorithm::ES256)?;
+    assert_invalid(authenticate(&authenticator, &token));
+
+    for path in [key, key_without_aud, key_expired] {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
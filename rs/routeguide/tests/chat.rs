@@ -0,0 +1,65 @@
+//! Chat notes shared by every `RouteChat` stream of a service, within limits.
+
+use futures::TryStreamExt;
+use grpc_util::{bootstrap::Bootstrap, serve::Server};
+use routeguide::{self as lib, route_guide_client::RouteGuideClient, Point, RouteNote};
+use tonic::transport::Channel;
+
+/// Serves `service` in memory.
+async fn serve(service: lib::server::RouteGuideService) -> anyhow::Result<Channel> {
+    let (server, connector) = Server::in_memory();
+    tokio::spawn(
+        Bootstrap::new()
+            .add_service(service.build(), lib::FILE_DESCRIPTOR_SET)
+            .serve_on(server),
+    );
+    connector.channel().await
+}
+
+fn note(latitude: i32, message: &str) -> RouteNote {
+    RouteNote {
+        location: Some(Point {
+            latitude,
+            longitude: 0,
+        }),
+        message: message.to_string(),
+    }
+}
+
+/// Sends `notes` on one stream, returning the messages received.
+async fn chat(channel: &Channel, notes: &[RouteNote]) -> anyhow::Result<Vec<String>> {
+    let mut client = RouteGuideClient::new(channel.clone());
+    let replies = client
+        .route_chat(futures::stream::iter(notes.to_vec()))
+        .await?
+        .into_inner();
+    Ok(replies.map_ok(|n| n.message).try_collect().await?)
+}
+
+#[tokio::test]
+async fn shares_notes_between_streams() -> anyhow::Result<()> {
+    let channel = serve(lib::server::RouteGuideService::new()).await?;
+    assert_eq!(
+        chat(&channel, &[note(1, "a"), note(2, "b")]).await?,
+        ["a", "b"]
+    );
+    // Another stream gets the notes of the first one at the same location.
+    assert_eq!(chat(&channel, &[note(1, "c")]).await?, ["a", "c"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn keeps_the_latest_notes_of_recent_locations() -> anyhow::Result<()> {
+    let service = lib::server::RouteGuideService::new().with_chat_history(2, 2);
+    let channel = serve(service).await?;
+    let replies = chat(&channel, &[note(1, "a"), note(1, "b"), note(1, "c")]).await?;
+    assert_eq!(replies, ["a", "a", "b", "b", "c"]);
+
+    // Only the 2 most recently written locations are kept.
+    chat(&channel, &[note(2, "d")]).await?;
+    assert_eq!(chat(&channel, &[note(1, "e")]).await?, ["c", "e"]);
+    chat(&channel, &[note(3, "f"), note(2, "g")]).await?;
+    assert_eq!(chat(&channel, &[note(1, "h")]).await?, ["h"]);
+    assert_eq!(chat(&channel, &[note(2, "i")]).await?, ["g", "i"]);
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! `routeguide-client` commands against an embedded server.
+
+use clap::Parser;
+use grpc_util::bootstrap::Bootstrap;
+use routeguide::{
+    cli::{self, Cli},
+    coordinates::{self, Coordinates, Unit},
+    embedded::EmbeddedServer,
+    Point,
+};
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+/// Runs `args` against `server` with `input` on stdin, returning stdout.
+async fn run(
+    server: &EmbeddedServer,
+    args: &[&str],
+    input: &'static str,
+) -> anyhow::Result<String> {
+    let cli =
+        Cli::try_parse_from(std::iter::once("routeguide-client").chain(args.iter().copied()))?;
+    let mut output = Vec::new();
+    cli.run(&mut server.client(), input.as_bytes(), &mut output)
+        .await?;
+    Ok(String::from_utf8(output)?)
+}
+
+#[test]
+fn parses_degrees_and_e7() -> anyhow::Result<()> {
+    let point = Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    let degrees: Coordinates = "40.9146138,-74.6188906".parse()?;
+    assert_eq!(degrees.point(Unit::Degrees)?, point);
+    let e7: Coordinates = "409146138, -746188906".parse()?;
+    assert_eq!(e7.point(Unit::E7)?, point);
+    assert_eq!(
+        coordinates::format_point(&point, Unit::Degrees),
+        "40.9146138,-74.6188906"
+    );
+
+    assert!("40.9".parse::<Coordinates>().is_err());
+    assert!(e7.point(Unit::Degrees).is_err(), "out of range");
+    assert!(degrees.point(Unit::E7).is_err(), "not an integer");
+
+    let route = "# start\n40.9146138,-74.6188906\n\n41.0,-74.0\n";
+    assert_eq!(cli::parse_route(route, Unit::Degrees)?.len(), 2);
+    let route = r#"[{"latitude": 1, "longitude": 2}]"#;
+    assert_eq!(cli::parse_route(route, Unit::Degrees)?[0].longitude, 2);
+    let route = r#"[{"location": {"latitude": 1, "longitude": 2}, "name": "x"}]"#;
+    assert_eq!(cli::parse_route(route, Unit::Degrees)?[0].latitude, 1);
+    let err = cli::parse_route("1,2\nnope\n", Unit::Degrees).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid point on line 2");
+    Ok(())
+}
+
+#[tokio::test]
+async fn prints_features_as_text_and_json() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let text = run(&server, &["get-feature", "40.9146138,-74.6188906"], "").await?;
+    assert_eq!(
+        text,
+        "40.9146138,-74.6188906\tBerkshire Valley Management Area Trail, Jefferson, NJ, USA\n"
+    );
+
+    let args = [
+        "list-features",
+        "--format=json",
+        "--e7",
+        "400000000,-750000000",
+        "420000000,-730000000",
+    ];
+    let json: serde_json::Value = serde_json::from_str(&run(&server, &args, "").await?)?;
+    let features = json.as_array().unwrap();
+    assert!(features.len() > 1, "{json}");
+    assert!(features.iter().all(|f| f["location"]["latitude"].is_i64()));
+
+    let args = ["get-feature", "--format=json", "1,2"];
+    let json: serde_json::Value = serde_json::from_str(&run(&server, &args, "").await?)?;
+    assert!(json.is_null(), "{json}");
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn records_routes_and_chats_from_stdin() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let route = "40.9146138,-74.6188906\n40.7838351,-74.6143763\n";
+    let args = ["record-route", "--file=-", "--format=ndjson"];
+    let summary: serde_json::Value = serde_json::from_str(&run(&server, &args, route).await?)?;
+    assert_eq!(summary["point_count"], 2);
+    assert_eq!(summary["feature_count"], 2);
+
+    let args = ["record-route", "--sample=3", "--db", DB_PATH];
+    let summary = run(&server, &args, "").await?;
+    assert!(summary.starts_with("points: 3\n"), "{summary}");
+
+    let notes = "1,2 first\nnot a note\n1,2 second\n";
+    let output = run(&server, &["route-chat", "--e7", "--format=ndjson"], notes).await?;
+    let received: Vec<serde_json::Value> = output
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let messages: Vec<_> = received.iter().map(|n| &n["message"]).collect();
+    // Each note comes back with the ones sent before at its location.
+    assert_eq!(messages, ["first", "first", "second"], "{output}");
+    server.shutdown().await
+}
@@ -0,0 +1,140 @@
+//! `grpc-encoding` negotiation between compressing and uncompressed peers.
+
+use futures::TryStreamExt;
+use grpc_util::compression::Compression;
+use routeguide as lib;
+use tonic::{codec::CompressionEncoding, transport::Channel};
+
+type Client = lib::route_guide_client::RouteGuideClient<Channel>;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+/// Serves `RouteGuide` with `compression` on an ephemeral port.
+async fn serve(compression: Compression) -> anyhow::Result<Channel> {
+    let service = lib::server::RouteGuideService::load(DB_PATH)?.with_compression(compression);
+    let router = grpc_util::routes::GrpcRouter::new()
+        .add_service(service.build())
+        .into_router();
+    let server = grpc_util::serve::Server::bind(([127, 0, 0, 1], 0).into()).await?;
+    let addr = server.local_addr()?;
+    tokio::spawn(server.serve(router));
+    let channel = Channel::from_shared(format!("http://{addr}"))?
+        .connect()
+        .await?;
+    Ok(channel)
+}
+
+fn client(channel: Channel, compression: Compression) -> Client {
+    compression.apply(
+        Client::new(channel),
+        Client::send_compressed,
+        Client::accept_compressed,
+    )
+}
+
+fn everywhere() -> lib::Rectangle {
+    lib::Rectangle {
+        lo: Some(lib::Point {
+            latitude: -900000000,
+            longitude: -1800000000,
+        }),
+        hi: Some(lib::Point {
+            latitude: 900000000,
+            longitude: 1800000000,
+        }),
+    }
+}
+
+fn route() -> impl futures::Stream<Item = lib::Point> {
+    let point = |latitude, longitude| lib::Point {
+        latitude,
+        longitude,
+    };
+    futures::stream::iter([point(409146138, -746188906), point(407838351, -746143763)])
+}
+
+/// Calls `ListFeatures` and `RecordRoute`, returning the `grpc-encoding` of the
+/// `ListFeatures` response, if any.
+async fn call(mut client: Client) -> anyhow::Result<Option<String>> {
+    let response = client.list_features(everywhere()).await?;
+    let encoding = response
+        .metadata()
+        .get("grpc-encoding")
+        .map(|v| v.to_str().unwrap().to_string());
+    let features: Vec<_> = response.into_inner().try_collect().await?;
+    assert!(features.len() > 100, "got {} features", features.len());
+    let summary = client.record_route(route()).await?.into_inner();
+    assert_eq!(summary.point_count, 2);
+    assert_eq!(summary.feature_count, 2);
+    Ok(encoding)
+}
+
+#[tokio::test]
+async fn uncompressed_client_and_compressing_server() -> anyhow::Result<()> {
+    let server = Compression::new().send(Some(CompressionEncoding::Gzip));
+    let channel = serve(server).await?;
+    let client = client(channel, Compression::new().accept([]));
+    assert_eq!(call(client).await?, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn compressing_client_and_uncompressed_server() -> anyhow::Result<()> {
+    let channel = serve(Compression::new()).await?;
+    let client = client(
+        channel,
+        Compression::new().send(Some(CompressionEncoding::Zstd)),
+    );
+    assert_eq!(call(client).await?, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn negotiated_encoding() -> anyhow::Result<()> {
+    for encoding in [CompressionEncoding::Gzip, CompressionEncoding::Zstd] {
+        let channel = serve(Compression::new().send(Some(encoding))).await?;
+        // The server answers in the first encoding listed by the client.
+        let client = client(
+            channel,
+            Compression::new().send(Some(encoding)).accept([encoding]),
+        );
+        assert_eq!(
+            call(client).await?.as_deref(),
+            Some(encoding.to_string()).as_deref()
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_compression_rejected_by_server_without_it() -> anyhow::Result<()> {
+    let channel = serve(Compression::new().accept([])).await?;
+    let mut client = client(
+        channel,
+        Compression::new().send(Some(CompressionEncoding::Gzip)),
+    );
+    let status = client.record_route(route()).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unimplemented);
+    Ok(())
+}
+
+#[tokio::test]
+async fn overrides_per_service() -> anyhow::Result<()> {
+    let zstd = Compression::new().send(Some(CompressionEncoding::Zstd));
+    let accept_zstd = || Compression::new().accept([CompressionEncoding::Zstd]);
+    let server = Compression::new().service("route_guide.RouteGuide", zstd.clone());
+    let channel = serve(server).await?;
+    assert_eq!(
+        call(client(channel, accept_zstd())).await?.as_deref(),
+        Some("zstd")
+    );
+
+    // Overrides of other services don't apply.
+    let server = Compression::new().service("helloworld.Greeter", zstd);
+    let channel = serve(server).await?;
+    assert_eq!(call(client(channel, accept_zstd())).await?, None);
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! Parsing of the `x-request-id` and `traceparent` headers.
+
+use grpc_util::context::{RequestId, TraceContext, REQUEST_ID_HEADER, TRACEPARENT_HEADER};
+use http::{HeaderMap, HeaderValue};
+
+const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+#[test]
+fn parses_traceparent() {
+    let context = TraceContext::parse(TRACEPARENT).unwrap();
+    assert_eq!(context.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(context.span_id(), "00f067aa0ba902b7");
+    assert!(context.is_sampled());
+    assert_eq!(context.to_string(), TRACEPARENT);
+    let unsampled = TRACEPARENT.replace("-01", "-00");
+    assert!(!TraceContext::parse(&unsampled).unwrap().is_sampled());
+
+    // Later versions may append fields.
+    let later = "cc-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-what-comes";
+    assert!(TraceContext::parse(later).is_some());
+
+    let mut headers = HeaderMap::new();
+    headers.insert(TRACEPARENT_HEADER, HeaderValue::from_static(TRACEPARENT));
+    assert_eq!(TraceContext::from_headers(&headers), Some(context));
+}
+
+#[test]
+fn rejects_invalid_traceparent() {
+    let invalid = [
+        "",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        // uppercase hex
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00F067AA0BA902B7-01",
+        "0A-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        // signs accepted by `u8::from_str_radix`
+        "00-+bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-+1",
+        // wrong lengths
+        "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b-01",
+        "000-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        // all-zero identifiers
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+    ];
+    for value in invalid {
+        assert_eq!(TraceContext::parse(value), None, "{value}");
+    }
+}
+
+#[test]
+fn reads_request_id() {
+    let request_id = |value: &'static [u8]| {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_bytes(value).unwrap());
+        RequestId::from_headers(&headers).map(|id| id.to_string())
+    };
+    assert_eq!(request_id(b"abc-123").as_deref(), Some("abc-123"));
+    assert_eq!(request_id(&[b'a'; 128]).map(|id| id.len()), Some(128));
+    assert_eq!(request_id(&[b'a'; 129]), None);
+    assert_eq!(request_id(b""), None);
+    assert_eq!(request_id(b"with space"), None);
+    assert_eq!(request_id(b"caf\xc3\xa9"), None);
+    assert_eq!(RequestId::from_headers(&HeaderMap::new()), None);
+
+    let generated = RequestId::new();
+    assert_eq!(generated.as_str().len(), 32);
+    assert_ne!(generated, RequestId::new());
+}
@@ -0,0 +1,79 @@
+//! CORS for gRPC-Web browser clients, configured by `CORS_ALLOWED_ORIGINS`.
+
+use grpc_util::bootstrap::Bootstrap;
+use http::{header, Method, StatusCode};
+use http_body_util::BodyExt;
+use prost::Message;
+use routeguide::{embedded::EmbeddedServer, Point};
+use tower::ServiceExt;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+const ORIGIN: &str = "https://routeguide.example";
+
+const GET_FEATURE: &str = "http://localhost/route_guide.RouteGuide/GetFeature";
+
+fn preflight(origin: &str) -> anyhow::Result<http::Request<tonic::body::BoxBody>> {
+    Ok(http::Request::builder()
+        .method(Method::OPTIONS)
+        .uri(GET_FEATURE)
+        .header(header::ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .header(
+            header::ACCESS_CONTROL_REQUEST_HEADERS,
+            "content-type,x-grpc-web,x-request-id",
+        )
+        .body(tonic::body::empty_body())?)
+}
+
+#[tokio::test]
+async fn answers_preflights_and_exposes_grpc_web_headers() -> anyhow::Result<()> {
+    // The only test of this binary, so that no other reads the environment.
+    std::env::set_var("CORS_ALLOWED_ORIGINS", ORIGIN);
+    let server = EmbeddedServer::start_in_memory(Bootstrap::from_env()?, DB_PATH).await?;
+
+    let response = server.channel().oneshot(preflight(ORIGIN)?).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], ORIGIN);
+    let allowed = headers[header::ACCESS_CONTROL_ALLOW_HEADERS].to_str()?;
+    assert!(allowed.contains("x-grpc-web"), "{allowed}");
+    assert!(allowed.contains("x-request-id"), "{allowed}");
+
+    let response = server
+        .channel()
+        .oneshot(preflight("https://elsewhere.example")?)
+        .await?;
+    assert!(!response
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+    // A length-prefixed message, as sent by gRPC-Web clients.
+    let point = Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    let mut body = vec![0];
+    body.extend((point.encoded_len() as u32).to_be_bytes());
+    point.encode(&mut body)?;
+    let request = http::Request::post(GET_FEATURE)
+        .header(header::ORIGIN, ORIGIN)
+        .header(header::CONTENT_TYPE, "application/grpc-web+proto")
+        .header("x-grpc-web", "1")
+        .body(tonic::body::boxed(http_body_util::Full::from(body)))?;
+    let response = server.channel().oneshot(request).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], ORIGIN);
+    let exposed = headers[header::ACCESS_CONTROL_EXPOSE_HEADERS].to_str()?;
+    assert!(exposed.contains("grpc-status"), "{exposed}");
+    let body = response.into_body().collect().await?.to_bytes();
+    // The message frame, then the trailers frame.
+    assert!(body
+        .windows(b"grpc-status:0".len())
+        .any(|w| w == b"grpc-status:0"));
+    server.shutdown().await
+}
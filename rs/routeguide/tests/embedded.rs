@@ -0,0 +1,62 @@
+//! The in-process server over TCP and in memory.
+
+use grpc_util::bootstrap::Bootstrap;
+use routeguide::{embedded::EmbeddedServer, Point};
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+async fn get_feature(server: &EmbeddedServer) -> anyhow::Result<()> {
+    let point = Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    let response = server.client().get_feature(point).await?;
+    // echoed by the request context layer
+    assert!(response.metadata().contains_key("x-request-id"));
+    assert_eq!(
+        response.into_inner().name,
+        "Berkshire Valley Management Area Trail, Jefferson, NJ, USA"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn serves_on_ephemeral_port() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start(Bootstrap::new(), DB_PATH).await?;
+    let addr = server.addr().unwrap();
+    assert!(addr.ip().is_loopback());
+    assert_ne!(addr.port(), 0);
+    get_feature(&server).await?;
+    server.shutdown().await?;
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn serves_in_memory() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    assert_eq!(server.addr(), None);
+    get_feature(&server).await?;
+
+    let mut health = tonic_health::pb::health_client::HealthClient::new(server.channel());
+    let request = tonic_health::pb::HealthCheckRequest {
+        service: routeguide::server::SERVICE_NAME.to_string(),
+    };
+    let status = health.check(request).await?.into_inner().status;
+    assert_eq!(
+        status,
+        tonic_health::pb::health_check_response::ServingStatus::Serving as i32
+    );
+
+    server.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn fails_to_start_without_features() {
+    let started = EmbeddedServer::start_in_memory(Bootstrap::new(), "missing.json").await;
+    assert!(started.is_err());
+}
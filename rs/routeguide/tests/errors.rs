@@ -0,0 +1,133 @@
+//! `google.rpc` error details of failed calls.
+
+use std::time::Duration;
+
+use grpc_util::{
+    bootstrap::Bootstrap,
+    errors::{Details, StatusExt},
+    limit::{LimitLayer, RateLimit},
+};
+use routeguide::{embedded::EmbeddedServer, server, Point, Rectangle, RouteNote};
+use tonic::Code;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+#[tokio::test]
+async fn not_found_names_the_resource() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let point = Point {
+        latitude: 1,
+        longitude: 2,
+    };
+    let status = server.client().get_feature(point).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let info = status.get_details_error_info().unwrap();
+    assert_eq!(info.reason, server::reasons::FEATURE_NOT_FOUND);
+    assert_eq!(info.domain, server::SERVICE_NAME);
+    assert_eq!(info.metadata["latitude"], "1");
+    let resource = status.get_details_resource_info().unwrap();
+    assert_eq!(resource.resource_type, "route_guide.Feature");
+    assert_eq!(resource.resource_name, "1,2");
+    assert_eq!(
+        Details::of(&status).to_string(),
+        "reason FEATURE_NOT_FOUND (route_guide.RouteGuide) latitude=1 longitude=2; \
+         route_guide.Feature 1,2: No feature at this location"
+    );
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn invalid_locations_list_field_violations() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let point = Point {
+        latitude: 910_000_000,
+        longitude: 0,
+    };
+    let status = server.client().get_feature(point).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let violations = status.get_details_bad_request().unwrap().field_violations;
+    let fields: Vec<_> = violations.iter().map(|v| v.field.as_str()).collect();
+    assert_eq!(fields, ["latitude"]);
+
+    let rect = Rectangle {
+        lo: None,
+        hi: Some(Point {
+            latitude: 0,
+            longitude: -1_900_000_000,
+        }),
+    };
+    let status = server.client().list_features(rect).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(
+        grpc_util::errors::reason(&status).as_deref(),
+        Some(server::reasons::INVALID_LOCATION)
+    );
+    let violations = status.get_details_bad_request().unwrap().field_violations;
+    let fields: Vec<_> = violations.iter().map(|v| v.field.as_str()).collect();
+    assert_eq!(fields, ["lo", "hi.longitude"]);
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn invalid_streamed_locations_end_the_call() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let fields = |status: tonic::Status| {
+        assert_eq!(status.code(), Code::InvalidArgument, "{status:?}");
+        let violations = status.get_details_bad_request().unwrap().field_violations;
+        violations.into_iter().map(|v| v.field).collect::<Vec<_>>()
+    };
+    let valid = Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    let invalid = Point {
+        latitude: 0,
+        longitude: 1_800_000_001,
+    };
+    let points = futures::stream::iter([valid, invalid]);
+    let status = server.client().record_route(points).await.unwrap_err();
+    assert_eq!(fields(status), ["longitude"]);
+
+    let note = |location| RouteNote {
+        location: Some(location),
+        message: "hi".to_string(),
+    };
+    let notes = futures::stream::iter([note(valid), note(invalid)]);
+    let mut replies = server.client().route_chat(notes).await?.into_inner();
+    // The note of the valid location may come back before the error.
+    let status = loop {
+        match replies.message().await {
+            Ok(Some(reply)) => assert_eq!(reply.location, Some(valid)),
+            Ok(None) => panic!("chat ended without error"),
+            Err(status) => break status,
+        }
+    };
+    assert_eq!(fields(status), ["location.longitude"]);
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn rate_limits_tell_when_to_retry() -> anyhow::Result<()> {
+    let limit = LimitLayer::new().rate(RateLimit::new(1.0, 1));
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new().limit(limit), DB_PATH).await?;
+    let point = Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    server.client().get_feature(point).await?;
+    let status = server.client().get_feature(point).await.unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    assert_eq!(
+        grpc_util::errors::reason(&status).as_deref(),
+        Some(grpc_util::errors::reasons::RATE_LIMITED)
+    );
+    let delay = grpc_util::errors::retry_delay(&status).unwrap();
+    assert!(
+        delay > Duration::ZERO && delay <= Duration::from_secs(1),
+        "{delay:?}"
+    );
+    server.shutdown().await
+}
@@ -0,0 +1,65 @@
+//! REST/JSON gateway, served next to the gRPC service.
+
+use grpc_util::{bootstrap::Bootstrap, serve::Server};
+use http_body_util::BodyExt;
+use routeguide as lib;
+use tonic::transport::Channel;
+use tower::ServiceExt;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+async fn serve() -> anyhow::Result<Channel> {
+    let service = lib::server::RouteGuideService::load(DB_PATH)?;
+    let (server, connector) = Server::in_memory();
+    tokio::spawn(
+        Bootstrap::new()
+            .http(lib::gateway::router(service.clone()))
+            .add_service(service.build(), lib::FILE_DESCRIPTOR_SET)
+            .serve_on(server),
+    );
+    connector.channel().await
+}
+
+/// Posts `body` to `/v1/routes`, returning the response status and JSON body.
+async fn post_route(
+    channel: &Channel,
+    body: serde_json::Value,
+) -> anyhow::Result<(http::StatusCode, serde_json::Value)> {
+    let request = http::Request::post("http://localhost/v1/routes")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(tonic::body::boxed(http_body_util::Full::from(
+            body.to_string(),
+        )))?;
+    let response = channel.clone().oneshot(request).await?;
+    let status = response.status();
+    let body = response.into_body().collect().await?.to_bytes();
+    Ok((status, serde_json::from_slice(&body)?))
+}
+
+#[tokio::test]
+async fn records_routes() -> anyhow::Result<()> {
+    let channel = serve().await?;
+    let points = serde_json::json!([
+        {"latitude": 409146138, "longitude": -746188906},
+        {"latitude": 407838351, "longitude": -746143763},
+    ]);
+    let (status, summary) = post_route(&channel, points).await?;
+    assert_eq!(status, http::StatusCode::OK, "{summary}");
+    assert_eq!(summary["point_count"], 2);
+    assert_eq!(summary["feature_count"], 2);
+    assert!(summary["distance"].as_i64() > Some(0), "{summary}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_invalid_route_points() -> anyhow::Result<()> {
+    let channel = serve().await?;
+    let points = serde_json::json!([{"latitude": 900000001, "longitude": 0}]);
+    let (status, error) = post_route(&channel, points).await?;
+    assert_eq!(status, http::StatusCode::BAD_REQUEST, "{error}");
+    assert_eq!(error["code"], tonic::Code::InvalidArgument as i32);
+    Ok(())
+}
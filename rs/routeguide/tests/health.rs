@@ -0,0 +1,112 @@
+//! `grpc.health.v1.Health` alongside `RouteGuide`.
+
+use std::time::Duration;
+
+use grpc_util::bootstrap::Bootstrap;
+use routeguide::{self as lib, embedded::EmbeddedServer};
+use tokio::io::AsyncWriteExt;
+use tonic_health::pb::{
+    health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+    HealthCheckResponse,
+};
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+fn request(service: &str) -> HealthCheckRequest {
+    HealthCheckRequest {
+        service: service.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn reports_not_serving_after_shutdown() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let mut health = HealthClient::new(server.channel());
+    let status = health.check(request(lib::server::SERVICE_NAME)).await?;
+    assert_eq!(status.into_inner().status(), ServingStatus::Serving);
+    let status = health.check(request("")).await?;
+    assert_eq!(status.into_inner().status(), ServingStatus::Serving);
+
+    // Watches outlive the trigger, for the grace period.
+    let mut service = health
+        .watch(request(lib::server::SERVICE_NAME))
+        .await?
+        .into_inner();
+    let mut overall = health.watch(request("")).await?.into_inner();
+    assert_eq!(
+        service.message().await?.unwrap().status(),
+        ServingStatus::Serving
+    );
+    assert_eq!(
+        overall.message().await?.unwrap().status(),
+        ServingStatus::Serving
+    );
+
+    let stopped = tokio::spawn(server.shutdown());
+    until(&mut service, ServingStatus::NotServing).await?;
+    until(&mut overall, ServingStatus::NotServing).await?;
+    drop((service, overall));
+    tokio::time::timeout(Duration::from_secs(5), stopped).await???;
+    Ok(())
+}
+
+/// Reads `watch` until it reports `status`.
+async fn until(
+    watch: &mut tonic::Streaming<HealthCheckResponse>,
+    status: ServingStatus,
+) -> anyhow::Result<()> {
+    let reported = async {
+        while let Some(response) = watch.message().await? {
+            if response.status() == status {
+                return Ok(());
+            }
+        }
+        anyhow::bail!("Watch ended before {status:?}")
+    };
+    tokio::time::timeout(Duration::from_secs(5), reported).await?
+}
+
+#[tokio::test]
+async fn reports_not_serving_while_loading() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let mut health = HealthClient::new(server.channel());
+    let mut watch = health
+        .watch(request(lib::server::SERVICE_NAME))
+        .await?
+        .into_inner();
+    until(&mut watch, ServingStatus::Serving).await?;
+
+    let (mut writer, reader) = tokio::io::duplex(1024);
+    let load = server.service().runtime_loader().with_reader(reader).load();
+    let loading = async {
+        until(&mut watch, ServingStatus::NotServing).await?;
+        let json = r#"[{"location": {"latitude": 1, "longitude": 2}, "name": "loaded"}]"#;
+        writer.write_all(json.as_bytes()).await?;
+        drop(writer);
+        anyhow::Ok(())
+    };
+    tokio::try_join!(load, loading)?;
+    until(&mut watch, ServingStatus::Serving).await?;
+    drop(watch);
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn keeps_serving_after_a_failed_reload() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let loaded = server
+        .service()
+        .runtime_loader()
+        .with_reader(&b"not json"[..])
+        .load()
+        .await;
+    assert!(loaded.is_err());
+
+    let mut health = HealthClient::new(server.channel());
+    let status = health.check(request(lib::server::SERVICE_NAME)).await?;
+    assert_eq!(status.into_inner().status(), ServingStatus::Serving);
+    server.shutdown().await
+}
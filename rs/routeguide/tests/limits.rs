@@ -0,0 +1,203 @@
+//! Rate and stream limits, over gRPC, plain HTTP and WebSockets.
+
+use std::{convert::Infallible, net::SocketAddr, time::Duration};
+
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use grpc_util::{
+    auth::Authenticator,
+    bootstrap::Bootstrap,
+    errors::reasons,
+    limit::{LimitLayer, RateLimit},
+    serve::Server,
+};
+use routeguide::{self as lib, embedded::EmbeddedServer, route_guide_client::RouteGuideClient};
+use tonic::{service::interceptor::InterceptedService, transport::Channel, Code, Request};
+use tower::{Layer, ServiceExt};
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+fn point() -> lib::Point {
+    lib::Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    }
+}
+
+/// Client authenticated with `token`.
+fn client(
+    channel: Channel,
+    token: &'static str,
+) -> RouteGuideClient<InterceptedService<Channel, impl tonic::service::Interceptor>> {
+    let authorize = move |mut req: Request<()>| {
+        let value = format!("Bearer {token}").parse().unwrap();
+        req.metadata_mut().insert("authorization", value);
+        Ok(req)
+    };
+    RouteGuideClient::new(InterceptedService::new(channel, authorize))
+}
+
+#[tokio::test]
+async fn caps_streams_per_client_and_in_total() -> anyhow::Result<()> {
+    let authenticator = Authenticator::new()
+        .api_key("a", "alice", ["write"])
+        .api_key("b", "bob", ["write"])
+        .api_key("c", "carol", ["write"]);
+    let bootstrap = Bootstrap::new()
+        .authenticator(Some(authenticator))
+        .limit(LimitLayer::new().max_streams_per_client(1).max_streams(2));
+    let server = EmbeddedServer::start_in_memory(bootstrap, DB_PATH).await?;
+    let too_many = |status: tonic::Status| {
+        assert_eq!(status.code(), Code::ResourceExhausted, "{status:?}");
+        assert_eq!(
+            grpc_util::errors::reason(&status).as_deref(),
+            Some(reasons::TOO_MANY_STREAMS)
+        );
+        status.message().to_string()
+    };
+
+    // Open until their request senders are dropped.
+    let chat = |token| {
+        let (tx, rx) = tokio::sync::mpsc::channel::<lib::RouteNote>(1);
+        let mut client = client(server.channel(), token);
+        async move {
+            let requests = tokio_stream::wrappers::ReceiverStream::new(rx);
+            let notes = client.route_chat(requests).await?.into_inner();
+            anyhow::Ok((tx, notes))
+        }
+    };
+    let (alice, mut alice_notes) = chat("a").await?;
+    let status = client(server.channel(), "a").get_feature(point()).await;
+    let message = too_many(status.unwrap_err());
+    assert_eq!(message, "Too many concurrent streams for alice");
+
+    let _bob = chat("b").await?;
+    let status = client(server.channel(), "c").get_feature(point()).await;
+    assert_eq!(too_many(status.unwrap_err()), "Too many concurrent streams");
+
+    drop(alice);
+    while alice_notes.try_next().await?.is_some() {}
+    client(server.channel(), "c").get_feature(point()).await?;
+    client(server.channel(), "a").get_feature(point()).await?;
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn charges_the_rate_of_admitted_calls_only() -> anyhow::Result<()> {
+    let authenticator = Authenticator::new().api_key("a", "alice", ["write"]);
+    let limit = LimitLayer::new()
+        .rate(RateLimit::new(0.001, 2))
+        .max_streams_per_client(1);
+    let bootstrap = Bootstrap::new()
+        .authenticator(Some(authenticator))
+        .limit(limit);
+    let server = EmbeddedServer::start_in_memory(bootstrap, DB_PATH).await?;
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<lib::RouteNote>(1);
+    let requests = tokio_stream::wrappers::ReceiverStream::new(rx);
+    let mut notes = client(server.channel(), "a")
+        .route_chat(requests)
+        .await?
+        .into_inner();
+    for _ in 0..3 {
+        let status = client(server.channel(), "a")
+            .get_feature(point())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            grpc_util::errors::reason(&status).as_deref(),
+            Some(reasons::TOO_MANY_STREAMS)
+        );
+    }
+    drop(tx);
+    while notes.try_next().await?.is_some() {}
+
+    // The second token of the burst is left.
+    client(server.channel(), "a").get_feature(point()).await?;
+    let status = client(server.channel(), "a")
+        .get_feature(point())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        grpc_util::errors::reason(&status).as_deref(),
+        Some(reasons::RATE_LIMITED)
+    );
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn keeps_limited_clients_among_many() -> anyhow::Result<()> {
+    let ok = tower::service_fn(|_: http::Request<()>| async {
+        Ok::<_, Infallible>(http::Response::new(String::new()))
+    });
+    let limit = LimitLayer::new().rate(RateLimit::new(0.001, 1));
+    let service = limit.layer(ok);
+    let call = |ip: [u8; 4]| {
+        let mut request = http::Request::new(());
+        let addr = SocketAddr::from((ip, 1234));
+        request
+            .extensions_mut()
+            .insert(axum::extract::ConnectInfo(addr));
+        let service = service.clone();
+        async move { anyhow::Ok(service.oneshot(request).await?.status()) }
+    };
+
+    assert_eq!(call([10, 0, 0, 1]).await?, http::StatusCode::OK);
+    assert_eq!(
+        call([10, 0, 0, 1]).await?,
+        http::StatusCode::TOO_MANY_REQUESTS
+    );
+    // Enough clients for idle buckets to be dropped, which the limited one isn't.
+    for i in 0..2000u16 {
+        let [hi, lo] = i.to_be_bytes();
+        assert_eq!(call([10, 1, hi, lo]).await?, http::StatusCode::OK);
+    }
+    assert_eq!(
+        call([10, 0, 0, 1]).await?,
+        http::StatusCode::TOO_MANY_REQUESTS
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn counts_websocket_chats_until_closed() -> anyhow::Result<()> {
+    let server = Server::bind(([127, 0, 0, 1], 0).into()).await?;
+    let addr = server.local_addr()?;
+    let service = lib::server::RouteGuideService::load(DB_PATH)?;
+    let bootstrap = Bootstrap::new()
+        .limit(LimitLayer::new().max_streams_per_client(1))
+        .add_service(service.clone().build(), lib::FILE_DESCRIPTOR_SET)
+        .http(lib::websocket::router(service));
+    let shutdown = bootstrap.shutdown().clone();
+    tokio::spawn(bootstrap.serve_on(server));
+    let channel = Channel::from_shared(format!("http://{addr}"))?
+        .connect()
+        .await?;
+    let mut client = RouteGuideClient::new(channel);
+
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/v1/chat")).await?;
+    let note = r#"{"location":{"latitude":1,"longitude":2},"message":"hi"}"#;
+    ws.send(note.into()).await?;
+    assert!(ws.next().await.transpose()?.is_some());
+    let status = client.get_feature(point()).await.unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted, "{status:?}");
+
+    ws.close(None).await?;
+    while ws.next().await.transpose()?.is_some() {}
+    // The permit is released once the server drops the connection.
+    let released = async {
+        loop {
+            match client.get_feature(point()).await {
+                Err(status) if status.code() == Code::ResourceExhausted => {
+                    tokio::time::sleep(Duration::from_millis(10)).await
+                }
+                res => return res,
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), released).await??;
+    shutdown.trigger();
+    Ok(())
+}
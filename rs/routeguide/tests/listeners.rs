@@ -0,0 +1,174 @@
+//! One server listening on IPv4, IPv6 and a Unix domain socket at once.
+#![cfg(unix)]
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use grpc_util::{
+    bootstrap::Bootstrap,
+    client::Target,
+    serve::{ConnectionInfo, ListenAddr, Server},
+};
+use routeguide as lib;
+use tonic::service::interceptor::InterceptedService;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+#[tokio::test]
+async fn serves_every_listener() -> anyhow::Result<()> {
+    let socket = std::env::temp_dir().join(format!("routeguide-{}.sock", std::process::id()));
+    let mut addrs = vec!["127.0.0.1:0".parse()?];
+    // Some CI hosts have no IPv6.
+    if std::net::TcpListener::bind("[::1]:0").is_ok() {
+        addrs.push("[::1]:0".parse()?);
+    }
+    addrs.push(ListenAddr::Unix(socket.clone()));
+    let server = Server::bind_all(&addrs).await?;
+    let bound = server.listen_addrs();
+    assert_eq!(bound.len(), addrs.len());
+    assert_eq!(bound.last(), Some(&ListenAddr::Unix(socket.clone())));
+
+    let bootstrap = Bootstrap::new();
+    let shutdown = bootstrap.shutdown().clone();
+    let service = lib::server::RouteGuideService::load(DB_PATH)?;
+    let serving = tokio::spawn(
+        bootstrap
+            .add_service(service.build(), lib::FILE_DESCRIPTOR_SET)
+            .serve_on(server),
+    );
+
+    let targets = bound.iter().map(|addr| match addr {
+        ListenAddr::Tcp(addr) => Target::Uri(format!("http://{addr}")),
+        ListenAddr::Unix(path) => Target::Unix(path.clone()),
+    });
+    for target in targets {
+        let channel = grpc_util::client::connect_to(target).await?;
+        let mut client = lib::route_guide_client::RouteGuideClient::new(channel);
+        let point = lib::Point {
+            latitude: 409146138,
+            longitude: -746188906,
+        };
+        let feature = client.get_feature(point).await?.into_inner();
+        assert!(!feature.name.is_empty());
+    }
+
+    shutdown.trigger();
+    serving.await??;
+    assert!(!socket.exists(), "socket left behind");
+    Ok(())
+}
+
+#[tokio::test]
+async fn handlers_see_connection_info() -> anyhow::Result<()> {
+    let socket = std::env::temp_dir().join(format!("routeguide-info-{}.sock", std::process::id()));
+    let addrs = ["127.0.0.1:0".parse()?, ListenAddr::Unix(socket.clone())];
+    let server = Server::bind_all(&addrs).await?;
+    let bound = server.listen_addrs();
+
+    // Interceptors get the request extensions handlers get.
+    let seen = Arc::<Mutex<Vec<(Option<SocketAddr>, ConnectionInfo)>>>::default();
+    let record = {
+        let seen = seen.clone();
+        move |request: tonic::Request<()>| {
+            let info = request.extensions().get::<ConnectionInfo>().cloned();
+            let info = info.ok_or_else(|| tonic::Status::internal("no connection info"))?;
+            seen.lock().unwrap().push((request.remote_addr(), info));
+            Ok(request)
+        }
+    };
+    let bootstrap = Bootstrap::new();
+    let shutdown = bootstrap.shutdown().clone();
+    let service = lib::server::RouteGuideService::load(DB_PATH)?;
+    let service = InterceptedService::new(service.build(), record);
+    let serving = tokio::spawn(
+        bootstrap
+            .add_service(service, lib::FILE_DESCRIPTOR_SET)
+            .serve_on(server),
+    );
+
+    let point = lib::Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    let targets = [
+        Target::Uri(format!("http://{}", bound[0])),
+        Target::Unix(socket.clone()),
+    ];
+    for target in targets {
+        let channel = grpc_util::client::connect_to(target).await?;
+        let mut client = lib::route_guide_client::RouteGuideClient::new(channel);
+        client.get_feature(point).await?;
+        client.get_feature(point).await?;
+    }
+
+    shutdown.trigger();
+    serving.await??;
+    let seen = seen.lock().unwrap();
+    let [(tcp_remote, tcp), (tcp_remote_again, tcp_again), (unix_remote, unix), _] = &seen[..]
+    else {
+        panic!("unexpected calls {seen:?}");
+    };
+    assert_eq!(tcp.local_addr(), Some(&bound[0]));
+    assert_eq!(*tcp_remote, tcp.peer_addr());
+    assert_eq!(tcp_remote.map(|a| a.ip()), Some([127, 0, 0, 1].into()));
+    assert_eq!(tcp_again.id(), tcp.id(), "same connection");
+    assert_eq!(tcp_remote_again, tcp_remote);
+    assert_eq!(unix.local_addr(), Some(&ListenAddr::Unix(socket)));
+    assert_eq!(*unix_remote, None);
+    assert_eq!(unix.peer_addr(), None);
+    assert_ne!(unix.id(), tcp.id());
+    Ok(())
+}
+
+#[tokio::test]
+async fn replaces_stale_sockets_only() -> anyhow::Result<()> {
+    let socket = std::env::temp_dir().join(format!("routeguide-stale-{}.sock", std::process::id()));
+    drop(std::os::unix::net::UnixListener::bind(&socket)?);
+    assert!(socket.exists());
+    let addrs = [ListenAddr::Unix(socket.clone())];
+    let server = Server::bind_all(&addrs).await?;
+
+    let in_use = Server::bind_all(&addrs).await.unwrap_err();
+    let cause = in_use.downcast_ref::<std::io::Error>().map(|e| e.kind());
+    assert_eq!(cause, Some(std::io::ErrorKind::AddrInUse), "{in_use:#}");
+    // The live server keeps its socket.
+    std::os::unix::net::UnixStream::connect(&socket)?;
+
+    drop(server);
+    std::fs::remove_file(&socket)?;
+    std::fs::write(&socket, "")?;
+    let not_socket = Server::bind_all(&addrs).await.unwrap_err();
+    assert!(
+        not_socket.to_string().contains("is not a socket"),
+        "{not_socket:#}"
+    );
+    std::fs::remove_file(&socket)?;
+    Ok(())
+}
+
+#[test]
+fn parses_listen_addrs_and_targets() -> anyhow::Result<()> {
+    assert_eq!(
+        "[::]:4772".parse::<ListenAddr>()?,
+        ListenAddr::Tcp("[::]:4772".parse()?)
+    );
+    for s in ["unix:/run/rg.sock", "unix:///run/rg.sock"] {
+        assert_eq!(
+            s.parse::<ListenAddr>()?,
+            ListenAddr::Unix("/run/rg.sock".into())
+        );
+        assert_eq!(s.parse::<Target>()?, Target::Unix("/run/rg.sock".into()));
+    }
+    assert!("localhost".parse::<ListenAddr>().is_err());
+    assert!("unix:".parse::<ListenAddr>().is_err());
+    assert_eq!(
+        "localhost:4772".parse::<Target>()?,
+        Target::HostPort("localhost:4772".to_string())
+    );
+    Ok(())
+}
@@ -0,0 +1,86 @@
+//! JSON log lines, as printed with `LOG_FORMAT=json`.
+
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use grpc_util::{bootstrap::Bootstrap, logging::JsonFormat};
+use routeguide::{embedded::EmbeddedServer, Point};
+use serde_json::Value;
+use tracing_subscriber::{fmt::format::JsonFields, layer::SubscriberExt};
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+/// Log output kept in memory.
+#[derive(Debug, Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Buffer {
+    fn lines(&self) -> anyhow::Result<Vec<Value>> {
+        let output = String::from_utf8(self.0.lock().unwrap().clone())?;
+        Ok(output
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?)
+    }
+}
+
+#[tokio::test]
+async fn prints_flat_json_objects_with_span_fields() -> anyhow::Result<()> {
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let layer = tracing_subscriber::fmt::layer()
+        .fmt_fields(JsonFields::new())
+        .event_format(JsonFormat)
+        .with_writer(move || writer.clone());
+    // Spawned tasks run on this thread, within the default subscriber.
+    let _default = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+    tracing::info!("Starting");
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let mut request = tonic::Request::new(Point {
+        latitude: 1,
+        longitude: 2,
+    });
+    request
+        .metadata_mut()
+        .insert("x-request-id", "logging-test".parse()?);
+    server.client().get_feature(request).await.unwrap_err();
+    server.shutdown().await?;
+
+    let lines = buffer.lines()?;
+    let outside = lines
+        .iter()
+        .find(|l| l["message"] == "Starting")
+        .unwrap_or_else(|| panic!("{lines:#?}"));
+    assert_eq!(outside["span"], Value::Null);
+    assert!(outside.as_object().unwrap().contains_key("span"));
+    let line = lines
+        .iter()
+        .find(|l| l["message"] == "No feature found")
+        .unwrap_or_else(|| panic!("{lines:#?}"));
+    let object = line.as_object().unwrap();
+    assert_eq!(line["level"], "INFO");
+    assert!(line["timestamp"].is_string(), "{line}");
+    assert!(line["target"].as_str().unwrap().starts_with("routeguide"));
+    assert!(line["span"].is_string(), "{line}");
+    assert_eq!(line["request_id"], "logging-test");
+    assert_eq!(line["rpc.service"], "route_guide.RouteGuide");
+    assert_eq!(line["rpc.method"], "GetFeature");
+    assert!(!object.keys().any(|k| k.starts_with("otel.")), "{line}");
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! Prometheus metrics of the calls, labelled by the methods served.
+
+use futures::TryStreamExt;
+use grpc_util::bootstrap::Bootstrap;
+use http_body_util::BodyExt;
+use routeguide::{embedded::EmbeddedServer, route_guide_client::RouteGuideClient, Point};
+use tower::ServiceExt;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+async fn send(
+    server: &EmbeddedServer,
+    request: http::Request<tonic::body::BoxBody>,
+) -> anyhow::Result<String> {
+    let body = server.channel().oneshot(request).await?.into_body();
+    let body = body.collect().await?.to_bytes();
+    Ok(String::from_utf8(body.to_vec())?)
+}
+
+#[tokio::test]
+async fn labels_calls_to_unknown_methods_as_unknown() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new().admin(true), DB_PATH).await?;
+    let mut client = RouteGuideClient::new(server.channel());
+    let point = Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    client.get_feature(point).await?;
+    for path in [
+        "/route_guide.RouteGuide/Random42",
+        "/random.Service42/Method",
+    ] {
+        let request = http::Request::post(format!("http://localhost{path}"))
+            .header(http::header::CONTENT_TYPE, "application/grpc")
+            .body(tonic::body::empty_body())?;
+        send(&server, request).await?;
+    }
+
+    let request = http::Request::get("http://localhost/metrics").body(tonic::body::empty_body())?;
+    let metrics = send(&server, request).await?;
+    let started = |labels: &str| {
+        metrics
+            .lines()
+            .any(|line| line.starts_with(&format!("grpc_server_started_total{{{labels}}}")))
+    };
+    assert!(
+        started(r#"grpc_method="GetFeature",grpc_service="route_guide.RouteGuide""#),
+        "{metrics}"
+    );
+    assert!(
+        started(r#"grpc_method="unknown",grpc_service="unknown""#),
+        "{metrics}"
+    );
+    assert!(!metrics.contains("Random42"), "{metrics}");
+    assert!(!metrics.contains("random.Service42"), "{metrics}");
+    server.shutdown().await
+}
+
+/// Value of the sample of `metrics` named `name`, labels included.
+fn sample(metrics: &str, name: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("No {name} in {metrics}"))
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn exports_call_and_load_families() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new().admin(true), DB_PATH).await?;
+    let rect = routeguide::Rectangle {
+        lo: Some(Point {
+            latitude: 400000000,
+            longitude: -750000000,
+        }),
+        hi: Some(Point {
+            latitude: 420000000,
+            longitude: -730000000,
+        }),
+    };
+    let mut client = RouteGuideClient::new(server.channel());
+    let features = client.list_features(rect).await?.into_inner();
+    let sent = features.try_collect::<Vec<_>>().await?.len();
+    assert!(sent > 1);
+    let failed = server
+        .service()
+        .runtime_loader()
+        .with_reader(&b"not json"[..])
+        .load()
+        .await;
+    assert!(failed.is_err());
+
+    let request = http::Request::get("http://localhost/metrics").body(tonic::body::empty_body())?;
+    let metrics = send(&server, request).await?;
+    let labels = r#"grpc_method="ListFeatures",grpc_service="route_guide.RouteGuide""#;
+    let count = sample(
+        &metrics,
+        &format!("grpc_server_handling_seconds_count{{{labels}}}"),
+    );
+    assert_eq!(count, 1.0);
+    assert!(metrics.contains(&format!(
+        "grpc_server_handling_seconds_bucket{{{labels},le="
+    )));
+    let in_flight = sample(
+        &metrics,
+        &format!("grpc_server_streams_in_flight{{{labels}}}"),
+    );
+    assert_eq!(in_flight, 0.0);
+    let sent_total = sample(&metrics, &format!("grpc_server_msg_sent_total{{{labels}}}"));
+    assert_eq!(sent_total, sent as f64);
+    let per_stream = format!(r#"grpc_server_msgs_per_stream_sum{{direction="sent",{labels}}}"#);
+    assert_eq!(sample(&metrics, &per_stream), sent as f64);
+
+    assert!(sample(&metrics, "route_guide_features") >= sent as f64);
+    assert!(sample(&metrics, "route_guide_loaded_features_total") >= sent as f64);
+    assert!(sample(&metrics, r#"route_guide_loads_total{result="success"}"#) >= 1.0);
+    assert!(sample(&metrics, r#"route_guide_loads_total{result="failure"}"#) >= 1.0);
+    assert!(sample(&metrics, "route_guide_load_duration_seconds_count") >= 2.0);
+    server.shutdown().await
+}
@@ -0,0 +1,51 @@
+//! Server reflection, served when enabled.
+
+use grpc_util::bootstrap::Bootstrap;
+use routeguide::{self as lib, embedded::EmbeddedServer};
+use tonic::Code;
+use tonic_reflection::pb::v1::{
+    server_reflection_client::ServerReflectionClient, server_reflection_request::MessageRequest,
+    server_reflection_response::MessageResponse, ServerReflectionRequest,
+};
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+/// Names of the services listed by the reflection service of `server`.
+async fn list_services(server: &EmbeddedServer) -> Result<Vec<String>, tonic::Status> {
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(MessageRequest::ListServices(String::new())),
+    };
+    let mut responses = ServerReflectionClient::new(server.channel())
+        .server_reflection_info(futures::stream::iter([request]))
+        .await?
+        .into_inner();
+    let response = responses.message().await?.expect("a response");
+    let Some(MessageResponse::ListServicesResponse(list)) = response.message_response else {
+        panic!("Unexpected response {response:?}");
+    };
+    Ok(list.service.into_iter().map(|s| s.name).collect())
+}
+
+#[tokio::test]
+async fn lists_services() -> anyhow::Result<()> {
+    let bootstrap = Bootstrap::new().reflection(true);
+    let server = EmbeddedServer::start_in_memory(bootstrap, DB_PATH).await?;
+    let services = list_services(&server).await?;
+    assert!(
+        services.iter().any(|s| s == lib::server::SERVICE_NAME),
+        "{services:?}"
+    );
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn is_disabled_by_default() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let status = list_services(&server).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unimplemented, "{status:?}");
+    server.shutdown().await
+}
@@ -0,0 +1,62 @@
+//! Interactive `RouteChat` sessions of `routeguide-client chat`.
+
+use clap::Parser;
+use grpc_util::bootstrap::Bootstrap;
+use routeguide::{cli::Cli, embedded::EmbeddedServer};
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+/// Runs a chat session typing `input`, returning what it printed.
+async fn chat(server: &EmbeddedServer, input: &'static str) -> anyhow::Result<String> {
+    let cli = Cli::try_parse_from(["routeguide-client", "chat", "--e7"])?;
+    let mut output = Vec::new();
+    cli.run(&mut server.client(), input.as_bytes(), &mut output)
+        .await?;
+    Ok(String::from_utf8(output)?)
+}
+
+#[tokio::test]
+async fn sends_notes_at_the_current_location() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let input = "hello?\n/at 1,2\nhello\n/move 3,4\n/at\n/history\n/jump\n/quit\nignored\n";
+    let output = chat(&server, input).await?;
+    // Notes received are printed as they come, between the other lines.
+    let (received, lines): (Vec<_>, Vec<_>) = output.lines().partition(|l| l.starts_with('<'));
+    assert_eq!(
+        lines,
+        [
+            "Connected, /help for commands",
+            "No location, set one with /at LAT,LON",
+            "At 1,2",
+            "At 3,4",
+            "At 3,4",
+            "> 1,2\thello",
+            "Unknown command /jump, /help for commands",
+            "Chat closed",
+        ],
+        "{output}"
+    );
+    // Notes in flight when quitting still come back.
+    assert_eq!(received, ["< 1,2\thello"], "{output}");
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn receives_notes_of_other_sessions() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    chat(&server, "/at 1,2\nfirst\n").await?;
+    let output = chat(&server, "/at 1,2\nsecond\n").await?;
+    let received: Vec<_> = output.lines().filter(|l| l.starts_with('<')).collect();
+    assert_eq!(received, ["< 1,2\tfirst", "< 1,2\tsecond"], "{output}");
+
+    let cli = Cli::try_parse_from(["routeguide-client", "chat", "--format=json"])?;
+    let err = cli
+        .run(&mut server.client(), tokio::io::empty(), Vec::new())
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Chat only prints text");
+    server.shutdown().await
+}
@@ -0,0 +1,49 @@
+//! gRPC services dispatched by path on one port.
+
+use grpc_util::bootstrap::Bootstrap;
+use helloworld::{client::GreeterClient, greeter::MyGreeter, server::GreeterServer};
+use routeguide::{embedded::EmbeddedServer, Point};
+use tonic::Code;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+#[tokio::test]
+async fn dispatches_services_by_path() -> anyhow::Result<()> {
+    let bootstrap = Bootstrap::new().reflection(true).add_service(
+        GreeterServer::new(MyGreeter),
+        helloworld::FILE_DESCRIPTOR_SET,
+    );
+    let server = EmbeddedServer::start_in_memory(bootstrap, DB_PATH).await?;
+
+    let request = helloworld::HelloRequest {
+        name: "Tonic".to_string(),
+    };
+    let reply = GreeterClient::new(server.channel())
+        .say_hello(request)
+        .await?;
+    assert_eq!(reply.into_inner().message, "Hello, Tonic!");
+    let point = Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    server.client().get_feature(point).await?;
+    let mut health = tonic_health::pb::health_client::HealthClient::new(server.channel());
+    health
+        .check(tonic_health::pb::HealthCheckRequest::default())
+        .await?;
+
+    // Over the same channel, a client of a service the server doesn't host.
+    let mut unknown = tonic::client::Grpc::new(server.channel());
+    unknown.ready().await?;
+    let path = http::uri::PathAndQuery::from_static("/pkg.Unknown/Method");
+    let codec = tonic::codec::ProstCodec::<(), ()>::default();
+    let status = unknown
+        .unary(tonic::Request::new(()), path, codec)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unimplemented, "{status:?}");
+    server.shutdown().await
+}
@@ -0,0 +1,98 @@
+//! Graceful shutdown: streams end, in-flight requests complete within the grace
+//! period, and the rest are cut off.
+
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use grpc_util::{bootstrap::Bootstrap, errors::reasons, serve::Server, shutdown::Shutdown};
+use routeguide::{self as lib, embedded::EmbeddedServer};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tonic::Code;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+#[tokio::test]
+async fn ends_streams_with_unavailable() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let (_tx, rx) = tokio::sync::mpsc::channel::<lib::RouteNote>(1);
+    let requests = tokio_stream::wrappers::ReceiverStream::new(rx);
+    let mut notes = server.client().route_chat(requests).await?.into_inner();
+
+    let shutdown = server.shutdown_handle();
+    let stopped = tokio::spawn(server.shutdown());
+    let status = notes.try_next().await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable, "{status:?}");
+    assert_eq!(
+        grpc_util::errors::reason(&status).as_deref(),
+        Some(reasons::SHUTTING_DOWN)
+    );
+    tokio::time::timeout(Duration::from_secs(5), stopped).await???;
+    assert!(shutdown.is_triggered());
+    Ok(())
+}
+
+/// Serves `GET /sleep`, answering after `sleep`, with `grace_period`.
+async fn serve_sleep(
+    sleep: Duration,
+    grace_period: Duration,
+) -> anyhow::Result<(
+    std::net::SocketAddr,
+    Shutdown,
+    tokio::task::JoinHandle<anyhow::Result<()>>,
+)> {
+    let shutdown = Shutdown::new();
+    let server = Server::bind(([127, 0, 0, 1], 0).into())
+        .await?
+        .graceful_shutdown(shutdown.clone(), grace_period);
+    let addr = server.local_addr()?;
+    let handler = move || async move {
+        tokio::time::sleep(sleep).await;
+        "done"
+    };
+    let router = axum::Router::new().route("/sleep", axum::routing::get(handler));
+    Ok((addr, shutdown, tokio::spawn(server.serve(router))))
+}
+
+/// Sends `GET /sleep` over HTTP/1.1, returning the raw response.
+async fn get_sleep(addr: std::net::SocketAddr) -> anyhow::Result<String> {
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    stream
+        .write_all(b"GET /sleep HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        .await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+#[tokio::test]
+async fn completes_requests_within_the_grace_period() -> anyhow::Result<()> {
+    let (addr, shutdown, serving) =
+        serve_sleep(Duration::from_millis(200), Duration::from_secs(5)).await?;
+    let request = tokio::spawn(get_sleep(addr));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown.trigger();
+
+    let response = request.await??;
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("done"), "{response}");
+    tokio::time::timeout(Duration::from_secs(5), serving).await???;
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn cuts_off_requests_after_the_grace_period() -> anyhow::Result<()> {
+    let (addr, shutdown, serving) =
+        serve_sleep(Duration::from_secs(60), Duration::from_millis(100)).await?;
+    let request = tokio::spawn(get_sleep(addr));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown.trigger();
+
+    tokio::time::timeout(Duration::from_secs(5), serving).await???;
+    let response = tokio::time::timeout(Duration::from_secs(5), request).await??;
+    assert!(!response.is_ok_and(|r| r.contains("done")));
+    Ok(())
+}
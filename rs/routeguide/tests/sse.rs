@@ -0,0 +1,178 @@
+//! Server-sent feature events: listing, updates and resynchronization.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use grpc_util::{bootstrap::Bootstrap, serve::Server};
+use http_body_util::BodyExt;
+use routeguide as lib;
+use tower::ServiceExt;
+
+/// Reads the events of a `text/event-stream` body.
+struct Events {
+    body: tonic::body::BoxBody,
+    buf: String,
+}
+
+impl Events {
+    /// Name and data of the next event, skipping comments.
+    async fn next(&mut self) -> anyhow::Result<(String, String)> {
+        loop {
+            if let Some(end) = self.buf.find("\n\n") {
+                let event: String = self.buf.drain(..end + 2).collect();
+                let field = |name: &str| {
+                    event
+                        .lines()
+                        .find_map(|l| l.strip_prefix(name))
+                        .map(|v| v.trim().to_string())
+                };
+                if let Some(name) = field("event:") {
+                    return Ok((name, field("data:").unwrap_or_default()));
+                }
+                continue;
+            }
+            let frame = self.body.frame().await.context("Stream ended")??;
+            if let Ok(data) = frame.into_data() {
+                self.buf.push_str(std::str::from_utf8(&data)?);
+            }
+        }
+    }
+
+    /// Names of the events up to and including the next `synced`, along with the
+    /// number of features among them.
+    async fn until_synced(&mut self) -> anyhow::Result<(Vec<String>, usize)> {
+        let mut names = Vec::new();
+        let mut features = 0;
+        loop {
+            let (name, _) = self.next().await?;
+            features += usize::from(name == "feature");
+            if name != "feature" {
+                names.push(name.clone());
+            }
+            if name == "synced" {
+                return Ok((names, features));
+            }
+        }
+    }
+}
+
+/// Adds a feature at each of `locations`.
+async fn add(
+    service: &lib::server::RouteGuideService,
+    locations: impl Iterator<Item = (i32, i32)>,
+) -> anyhow::Result<()> {
+    let features: Vec<_> = locations
+        .map(|(latitude, longitude)| {
+            serde_json::json!({
+                "location": {"latitude": latitude, "longitude": longitude},
+                "name": format!("{latitude},{longitude}"),
+            })
+        })
+        .collect();
+    let json = serde_json::to_vec(&features)?;
+    service.runtime_loader().with_reader(&json[..]).load().await
+}
+
+#[tokio::test]
+async fn follows_features_and_resyncs_after_lagging() -> anyhow::Result<()> {
+    let service = lib::server::RouteGuideService::new();
+    add(&service, [(1, 1), (2, 2), (-1, -1)].into_iter()).await?;
+    let (server, connector) = Server::in_memory();
+    let bootstrap = Bootstrap::new().http(lib::sse::router(service.clone()));
+    let shutdown = bootstrap.shutdown().clone();
+    tokio::spawn(
+        bootstrap
+            .add_service(service.clone().build(), lib::FILE_DESCRIPTOR_SET)
+            .serve_on(server),
+    );
+    let channel = connector.channel().await?;
+    let uri = "http://localhost/v1/features/events?lo_lat=0&lo_lon=0&hi_lat=1000&hi_lon=1000";
+    let request = http::Request::get(uri).body(tonic::body::empty_body())?;
+    let response = channel.oneshot(request).await?;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    let mut events = Events {
+        body: response.into_body(),
+        buf: String::new(),
+    };
+
+    assert_eq!(
+        events.until_synced().await?,
+        (vec!["synced".to_string()], 2)
+    );
+    add(&service, [(3, 3), (-3, 3)].into_iter()).await?;
+    let (name, data) = events.next().await?;
+    assert_eq!(name, "feature");
+    assert!(data.contains(r#""name":"3,3""#), "{data}");
+
+    // More than the updates buffered while the events aren't read.
+    add(&service, (10..400).map(|i| (i, i))).await?;
+    let (names, features) = events.until_synced().await?;
+    assert_eq!(names, ["resync", "synced"]);
+    assert_eq!(features, 3 + 390);
+    // Features already listed aren't sent again.
+    let next = tokio::time::timeout(Duration::from_millis(200), events.next()).await;
+    assert!(next.is_err(), "{next:?}");
+
+    shutdown.trigger();
+    Ok(())
+}
+
+#[tokio::test]
+async fn sends_features_added_at_listed_locations() -> anyhow::Result<()> {
+    let service = lib::server::RouteGuideService::new();
+    add(&service, [(1, 1), (2, 2)].into_iter()).await?;
+    let uri = "http://localhost/v1/features/events?lo_lat=0&lo_lon=0&hi_lat=1000&hi_lon=1000";
+    let request = http::Request::get(uri).body(axum::body::Body::empty())?;
+    let response = lib::sse::router(service.clone()).oneshot(request).await?;
+    let mut events = Events {
+        body: tonic::body::boxed(response.into_body()),
+        buf: String::new(),
+    };
+
+    assert_eq!(
+        events.until_synced().await?,
+        (vec!["synced".to_string()], 2)
+    );
+    // Queued before the stream resumes after the listing, as if added during it, at
+    // the location of a listed feature.
+    let json = br#"[{"location": {"latitude": 1, "longitude": 1}, "name": "new"}]"#;
+    service
+        .runtime_loader()
+        .with_reader(&json[..])
+        .load()
+        .await?;
+    let next = tokio::time::timeout(Duration::from_secs(1), events.next()).await??;
+    assert_eq!(next.0, "feature");
+    assert!(next.1.contains(r#""name":"new""#), "{next:?}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn authenticates_before_validating() -> anyhow::Result<()> {
+    let service = lib::server::RouteGuideService::new();
+    let (server, connector) = Server::in_memory();
+    let authenticator = grpc_util::auth::Authenticator::new().api_key("r", "reader", ["read"]);
+    let bootstrap = Bootstrap::new()
+        .authenticator(Some(authenticator))
+        .policy(lib::server::default_policy())
+        .http(lib::sse::router(service.clone()));
+    let shutdown = bootstrap.shutdown().clone();
+    tokio::spawn(
+        bootstrap
+            .add_service(service.build(), lib::FILE_DESCRIPTOR_SET)
+            .serve_on(server),
+    );
+    let channel = connector.channel().await?;
+    let uri = "http://localhost/v1/features/events";
+    let request = http::Request::get(uri).body(tonic::body::empty_body())?;
+    let response = channel.clone().oneshot(request).await?;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let request = http::Request::get(uri)
+        .header(http::header::AUTHORIZATION, "Bearer r")
+        .body(tonic::body::empty_body())?;
+    let response = channel.oneshot(request).await?;
+    assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+    shutdown.trigger();
+    Ok(())
+}
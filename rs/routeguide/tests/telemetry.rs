@@ -0,0 +1,144 @@
+//! OTLP export of client, server and handler spans, received in-process.
+
+use std::sync::{Arc, Mutex};
+
+use opentelemetry_proto::tonic::{
+    collector::trace::v1::{
+        trace_service_server::{TraceService, TraceServiceServer},
+        ExportTraceServiceRequest, ExportTraceServiceResponse,
+    },
+    common::v1::any_value::Value,
+    trace::v1::{span::SpanKind, Span},
+};
+use routeguide as lib;
+use tower::ServiceBuilder;
+use tracing::Instrument;
+use tracing_subscriber::layer::SubscriberExt;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+/// OTLP trace receiver keeping every exported span.
+#[derive(Debug, Clone, Default)]
+struct Receiver {
+    spans: Arc<Mutex<Vec<Span>>>,
+}
+
+#[tonic::async_trait]
+impl TraceService for Receiver {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportTraceServiceRequest>,
+    ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+        let spans = request
+            .into_inner()
+            .resource_spans
+            .into_iter()
+            .flat_map(|r| r.scope_spans)
+            .flat_map(|s| s.spans);
+        self.spans.lock().unwrap().extend(spans);
+        Ok(tonic::Response::new(Default::default()))
+    }
+}
+
+async fn bind() -> anyhow::Result<(grpc_util::serve::Server, String)> {
+    let server = grpc_util::serve::Server::bind(([127, 0, 0, 1], 0).into()).await?;
+    let endpoint = format!("http://{}", server.local_addr()?);
+    Ok((server, endpoint))
+}
+
+fn attribute<'a>(span: &'a Span, key: &str) -> Option<&'a Value> {
+    let attribute = span.attributes.iter().find(|a| a.key == key)?;
+    attribute.value.as_ref()?.value.as_ref()
+}
+
+fn find<'a>(spans: &'a [Span], name: &str) -> &'a Span {
+    spans
+        .iter()
+        .find(|s| s.name == name)
+        .unwrap_or_else(|| panic!("no {name} span in {spans:#?}"))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn exports_client_server_and_handler_spans() -> anyhow::Result<()> {
+    let receiver = Receiver::default();
+    let (server, otlp_endpoint) = bind().await?;
+    let router = grpc_util::routes::GrpcRouter::new()
+        .add_service(TraceServiceServer::new(receiver.clone()))
+        .into_router();
+    tokio::spawn(server.serve(router));
+
+    let provider = grpc_util::telemetry::tracer_provider(&otlp_endpoint, "routeguide-test")?;
+    let subscriber = tracing_subscriber::registry().with(grpc_util::telemetry::layer(&provider));
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    let service = lib::server::RouteGuideService::load(DB_PATH)?;
+    let router = grpc_util::routes::GrpcRouter::new()
+        .add_service(service.build())
+        .into_router();
+    let trace_layer = tower_http::trace::TraceLayer::new_for_grpc()
+        .make_span_with(grpc_util::context::MakeRequestSpan);
+    let service = ServiceBuilder::new()
+        .layer(grpc_util::context::RequestContextLayer::new())
+        .layer(trace_layer)
+        .layer(grpc_util::metrics::MetricsLayer::new())
+        .service(router);
+    let (server, endpoint) = bind().await?;
+    tokio::spawn(server.serve(service));
+
+    let channel = tonic::transport::Channel::from_shared(endpoint)?
+        .connect()
+        .await?;
+    let channel = tonic::service::interceptor::InterceptedService::new(
+        channel,
+        grpc_util::client::RequestMetadata::default(),
+    );
+    let mut client = lib::route_guide_client::RouteGuideClient::new(channel);
+    let point = lib::Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    client
+        .get_feature(point)
+        .instrument(tracing::info_span!("client", otel.kind = "client"))
+        .await?;
+
+    tokio::task::spawn_blocking(move || provider.shutdown()).await??;
+    let spans = receiver.spans.lock().unwrap().clone();
+    let client = find(&spans, "client");
+    let server = find(&spans, "route_guide.RouteGuide/GetFeature");
+    let handler = find(&spans, "get_feature");
+
+    assert_eq!(client.kind, SpanKind::Client as i32);
+    assert_eq!(server.kind, SpanKind::Server as i32);
+    assert_eq!(server.trace_id, client.trace_id);
+    assert_eq!(server.parent_span_id, client.span_id);
+    assert_eq!(handler.trace_id, client.trace_id);
+    assert_eq!(handler.parent_span_id, server.span_id);
+
+    let string = |s: &str| Some(Value::StringValue(s.to_string()));
+    assert_eq!(attribute(server, "rpc.system").cloned(), string("grpc"));
+    assert_eq!(
+        attribute(server, "rpc.service").cloned(),
+        string("route_guide.RouteGuide")
+    );
+    assert_eq!(
+        attribute(server, "rpc.method").cloned(),
+        string("GetFeature")
+    );
+    assert_eq!(
+        attribute(server, "rpc.grpc.status_code"),
+        Some(&Value::IntValue(0))
+    );
+    assert_eq!(
+        attribute(server, "rpc.message.received_count"),
+        Some(&Value::IntValue(1))
+    );
+    assert_eq!(
+        attribute(server, "rpc.message.sent_count"),
+        Some(&Value::IntValue(1))
+    );
+    Ok(())
+}
@@ -0,0 +1,204 @@
+//! TLS and mutual TLS, with certificates generated for each test.
+
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use grpc_util::{
+    bootstrap::Bootstrap,
+    serve::Server,
+    shutdown::Shutdown,
+    tls::{PeerIdentity, ServerTlsConfig},
+};
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    KeyUsagePurpose,
+};
+use routeguide as lib;
+use tonic::{
+    service::interceptor::InterceptedService,
+    transport::{Certificate, Channel, ClientTlsConfig, Identity},
+};
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+/// PEM certificates and keys of a CA, and of a server and a client it signed, in a
+/// directory removed on drop.
+struct Pki {
+    dir: PathBuf,
+    ca: String,
+    client_cert: String,
+    client_key: String,
+}
+
+impl Pki {
+    fn generate(name: &str) -> anyhow::Result<Self> {
+        let ca_key = KeyPair::generate()?;
+        let mut params = CertificateParams::new(Vec::<String>::new())?;
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "routeguide test CA");
+        let ca = params.self_signed(&ca_key)?;
+
+        let server_key = KeyPair::generate()?;
+        let mut params = CertificateParams::new(vec!["localhost".to_string()])?;
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        let server = params.signed_by(&server_key, &ca, &ca_key)?;
+
+        let client_key = KeyPair::generate()?;
+        let mut params = CertificateParams::new(vec!["tracker.example".to_string()])?;
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "tracker");
+        let client = params.signed_by(&client_key, &ca, &ca_key)?;
+
+        let dir = std::env::temp_dir().join(format!("routeguide-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("ca.pem"), ca.pem())?;
+        std::fs::write(dir.join("server.pem"), server.pem())?;
+        std::fs::write(dir.join("server.key"), server_key.serialize_pem())?;
+        Ok(Self {
+            dir,
+            ca: ca.pem(),
+            client_cert: client.pem(),
+            client_key: client_key.serialize_pem(),
+        })
+    }
+
+    fn server_config(&self) -> ServerTlsConfig {
+        ServerTlsConfig::new(self.dir.join("server.pem"), self.dir.join("server.key"))
+    }
+
+    fn client_ca(&self) -> PathBuf {
+        self.dir.join("ca.pem")
+    }
+
+    /// Trusts the CA, presenting the client certificate when `identity` is set.
+    fn client_config(&self, identity: bool) -> ClientTlsConfig {
+        let config = ClientTlsConfig::new()
+            .ca_certificate(Certificate::from_pem(&self.ca))
+            .domain_name("localhost");
+        if identity {
+            config.identity(Identity::from_pem(&self.client_cert, &self.client_key))
+        } else {
+            config
+        }
+    }
+}
+
+impl Drop for Pki {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+type Identities = Arc<Mutex<Vec<Option<PeerIdentity>>>>;
+
+/// Serves `RouteGuide` with `tls` on an ephemeral port, recording the peer identity
+/// of each call.
+async fn serve(tls: ServerTlsConfig) -> anyhow::Result<(SocketAddr, Shutdown, Identities)> {
+    let server = Server::bind(([127, 0, 0, 1], 0).into())
+        .await?
+        .tls(Some(tls.build()?));
+    let addr = server.local_addr()?;
+    let identities = Identities::default();
+    let record = {
+        let identities = identities.clone();
+        move |request: tonic::Request<()>| {
+            let identity = request.extensions().get::<PeerIdentity>().cloned();
+            identities.lock().unwrap().push(identity);
+            Ok(request)
+        }
+    };
+    let bootstrap = Bootstrap::new();
+    let shutdown = bootstrap.shutdown().clone();
+    let service = lib::server::RouteGuideService::load(DB_PATH)?;
+    let service = InterceptedService::new(service.build(), record);
+    tokio::spawn(
+        bootstrap
+            .add_service(service, lib::FILE_DESCRIPTOR_SET)
+            .serve_on(server),
+    );
+    Ok((addr, shutdown, identities))
+}
+
+/// Connects to `addr` and gets a feature, with TLS when `tls` is set.
+async fn get_feature(addr: SocketAddr, tls: Option<ClientTlsConfig>) -> anyhow::Result<()> {
+    let channel = match tls {
+        Some(tls) => Channel::from_shared(format!("https://{addr}"))?.tls_config(tls)?,
+        None => Channel::from_shared(format!("http://{addr}"))?,
+    };
+    let mut client = lib::route_guide_client::RouteGuideClient::new(channel.connect().await?);
+    let point = lib::Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    };
+    client.get_feature(point).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn serves_tls_clients_only() -> anyhow::Result<()> {
+    let pki = Pki::generate("tls")?;
+    let (addr, shutdown, identities) = serve(pki.server_config()).await?;
+
+    assert!(get_feature(addr, None).await.is_err(), "plaintext client");
+    get_feature(addr, Some(pki.client_config(false))).await?;
+    // Without a client CA, client certificates are ignored.
+    get_feature(addr, Some(pki.client_config(true))).await?;
+    assert_eq!(identities.lock().unwrap().len(), 2);
+    assert!(identities.lock().unwrap().iter().all(Option::is_none));
+
+    shutdown.trigger();
+    Ok(())
+}
+
+#[tokio::test]
+async fn identifies_mtls_clients() -> anyhow::Result<()> {
+    let pki = Pki::generate("mtls")?;
+    let (addr, shutdown, identities) =
+        serve(pki.server_config().client_ca(pki.client_ca())).await?;
+
+    get_feature(addr, Some(pki.client_config(true))).await?;
+    let identity = identities.lock().unwrap().pop().flatten().unwrap();
+    assert_eq!(identity.common_name(), Some("tracker"));
+    assert_eq!(identity.subject_alt_names(), ["tracker.example"]);
+    assert_eq!(identity.to_string(), "tracker");
+
+    let anonymous = get_feature(addr, Some(pki.client_config(false))).await;
+    assert!(anonymous.is_err(), "client without certificate");
+    assert!(identities.lock().unwrap().is_empty());
+
+    shutdown.trigger();
+    Ok(())
+}
+
+#[tokio::test]
+async fn accepts_clients_without_certificate_when_optional() -> anyhow::Result<()> {
+    let pki = Pki::generate("mtls-optional")?;
+    let config = pki
+        .server_config()
+        .client_ca(pki.client_ca())
+        .client_auth_optional(true);
+    let (addr, shutdown, identities) = serve(config).await?;
+
+    get_feature(addr, Some(pki.client_config(false))).await?;
+    get_feature(addr, Some(pki.client_config(true))).await?;
+    let identities = identities.lock().unwrap();
+    assert!(identities[0].is_none());
+    assert_eq!(
+        identities[1].as_ref().and_then(|i| i.name()),
+        Some("tracker")
+    );
+
+    shutdown.trigger();
+    Ok(())
+}
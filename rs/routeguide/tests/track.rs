@@ -0,0 +1,171 @@
+//! GPX and GeoJSON tracks replayed into `RecordRoute`.
+
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use futures::StreamExt;
+use grpc_util::bootstrap::Bootstrap;
+use routeguide::{cli::Cli, embedded::EmbeddedServer, track, Point};
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+const GPX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+  <trk>
+    <name>Jefferson</name>
+    <trkseg>
+      <trkpt lat="40.9146138" lon="-74.6188906">
+        <ele>250</ele>
+        <time>2024-05-01T10:00:00Z</time>
+      </trkpt>
+      <trkpt lat="40.7838351" lon="-74.6143763">
+        <time>2024-05-01T10:00:10Z</time>
+      </trkpt>
+    </trkseg>
+    <trkseg>
+      <trkpt lat="40.7838351" lon="-74.6143763">
+        <time>2024-05-01T10:00:20Z</time>
+      </trkpt>
+    </trkseg>
+  </trk>
+</gpx>
+"#;
+
+const GEOJSON: &str = r#"{
+  "type": "FeatureCollection",
+  "features": [
+    {
+      "type": "Feature",
+      "properties": { "coordTimes": ["2024-05-01T10:00:00Z", "2024-05-01T12:00:00+02:00"] },
+      "geometry": {
+        "type": "LineString",
+        "coordinates": [[-74.6188906, 40.9146138, 250.0], [-74.6143763, 40.7838351]]
+      }
+    },
+    { "type": "Feature", "properties": {}, "geometry": { "type": "Point", "coordinates": [0, 0] } }
+  ]
+}"#;
+
+fn jefferson() -> Point {
+    Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    }
+}
+
+#[test]
+fn parses_gpx_tracks() -> anyhow::Result<()> {
+    let track = track::parse_gpx(GPX)?;
+    assert_eq!(track.len(), 3);
+    assert_eq!(track[0].point, jefferson());
+    let elapsed = track[2].time.unwrap() - track[0].time.unwrap();
+    assert_eq!(elapsed, time::Duration::seconds(20));
+
+    let err = track::parse_gpx(&GPX.replace(r#"lon="-74.6188906""#, "")).unwrap_err();
+    assert_eq!(format!("{err:#}"), "Invalid trkpt at 6:7: Missing lon");
+    Ok(())
+}
+
+#[test]
+fn parses_geojson_line_strings() -> anyhow::Result<()> {
+    let track = track::parse_geojson(GEOJSON)?;
+    assert_eq!(track.len(), 2);
+    assert_eq!(track[0].point, jefferson());
+    assert_eq!(
+        track[1].time.unwrap() - track[0].time.unwrap(),
+        time::Duration::ZERO
+    );
+
+    let geometry = r#"{"type": "LineString", "coordinates": [[-74.6188906, 40.9146138]]}"#;
+    assert_eq!(track::parse_geojson(geometry)?[0].time, None);
+    let err =
+        track::parse_geojson(&GEOJSON.replace(r#", "2024-05-01T12:00:00+02:00""#, "")).unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "In feature 0: 1 timestamps for 2 positions"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn replays_at_the_pace_of_timestamps() -> anyhow::Result<()> {
+    let track = track::parse_gpx(GPX)?;
+    let start = Instant::now();
+    let points: Vec<_> = track::replay(track.clone(), None).collect().await;
+    assert_eq!(points.len(), 3);
+    assert!(start.elapsed() < Duration::from_millis(100));
+
+    // 20 seconds at 100 times the speed.
+    let start = Instant::now();
+    let points: Vec<_> = track::replay(track, Some(100.0)).collect().await;
+    assert_eq!(points.len(), 3);
+    assert!(
+        start.elapsed() >= Duration::from_millis(200),
+        "{:?}",
+        start.elapsed()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn holds_points_due_beyond_the_clock() -> anyhow::Result<()> {
+    let track = track::parse_gpx(GPX)?;
+    let mut points = std::pin::pin!(track::replay(track, Some(1e-300)));
+    assert!(points.next().await.is_some());
+    let next = tokio::time::timeout(Duration::from_millis(50), points.next()).await;
+    assert!(next.is_err(), "{next:?}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn paces_replays_from_the_first_point() -> anyhow::Result<()> {
+    let track = track::parse_gpx(GPX)?;
+    let mut points = std::pin::pin!(track::replay(track, Some(100.0)));
+    // Stands for connecting the call before the first point is sent.
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    assert!(points.next().await.is_some());
+    let start = Instant::now();
+    assert_eq!(points.collect::<Vec<_>>().await.len(), 2);
+    assert!(
+        start.elapsed() >= Duration::from_millis(150),
+        "{:?}",
+        start.elapsed()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn records_tracks() -> anyhow::Result<()> {
+    let server = EmbeddedServer::start_in_memory(Bootstrap::new(), DB_PATH).await?;
+    let path = std::env::temp_dir().join(format!("routeguide-{}.gpx", std::process::id()));
+    std::fs::write(&path, GPX)?;
+    let args = [
+        "routeguide-client",
+        "record-route",
+        "--format=ndjson",
+        "--track",
+        path.to_str().unwrap(),
+        "--realtime",
+        "--speed=1000",
+    ];
+    let mut output = Vec::new();
+    let result = Cli::try_parse_from(args)?
+        .run(&mut server.client(), tokio::io::empty(), &mut output)
+        .await;
+    std::fs::remove_file(&path)?;
+    result?;
+    let summary: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(summary["point_count"], 3);
+    assert_eq!(summary["feature_count"], 3);
+
+    let args = ["routeguide-client", "record-route", "--speed=2"];
+    assert!(
+        Cli::try_parse_from(args).is_err(),
+        "--speed requires --realtime"
+    );
+    server.shutdown().await
+}
@@ -0,0 +1,118 @@
+//! HTTP/2 settings and message size limits of servers and clients.
+
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use grpc_util::transport::{Http2Config, MessageSize};
+use routeguide as lib;
+use tonic::{transport::Channel, Code};
+
+type Client = lib::route_guide_client::RouteGuideClient<Channel>;
+
+const DB_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../data/route_guide_db.json"
+);
+
+/// Small windows and frequent pings, which calls must get through.
+fn http2() -> Http2Config {
+    Http2Config::new()
+        .keep_alive(
+            Some(Duration::from_millis(50)),
+            Some(Duration::from_secs(1)),
+        )
+        .max_concurrent_streams(Some(4))
+        .initial_window_sizes(Some(1024), Some(4096))
+        .max_frame_size(Some(16 * 1024))
+        .max_header_list_size(Some(16 * 1024))
+}
+
+/// Serves `RouteGuide` with `message_size` and [`http2`] on an ephemeral port.
+async fn serve(message_size: MessageSize) -> anyhow::Result<Channel> {
+    let service = lib::server::RouteGuideService::load(DB_PATH)?.with_message_size(message_size);
+    let router = grpc_util::routes::GrpcRouter::new()
+        .add_service(service.build())
+        .into_router();
+    let server = grpc_util::serve::Server::bind(([127, 0, 0, 1], 0).into())
+        .await?
+        .http2(http2());
+    let addr = server.local_addr()?;
+    tokio::spawn(server.serve(router));
+    let endpoint = Channel::from_shared(format!("http://{addr}"))?;
+    Ok(http2().apply_endpoint(endpoint).connect().await?)
+}
+
+fn point() -> lib::Point {
+    lib::Point {
+        latitude: 409146138,
+        longitude: -746188906,
+    }
+}
+
+#[tokio::test]
+async fn streams_through_small_windows_and_pings() -> anyhow::Result<()> {
+    let mut client = Client::new(serve(MessageSize::new()).await?);
+    let rect = lib::Rectangle {
+        lo: Some(lib::Point {
+            latitude: -900000000,
+            longitude: -1800000000,
+        }),
+        hi: Some(lib::Point {
+            latitude: 900000000,
+            longitude: 1800000000,
+        }),
+    };
+    let mut stream = client.list_features(rect).await?.into_inner();
+    let mut count = 0;
+    while stream.try_next().await?.is_some() {
+        count += 1;
+        // Leaves time for a few pings.
+        if count == 1 {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+    assert!(count > 1, "{count} features");
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_messages_over_the_limit() -> anyhow::Result<()> {
+    let message_size = MessageSize::new().max_decoding(Some(4));
+    let mut client = Client::new(serve(message_size).await?);
+    let status = client.get_feature(point()).await.unwrap_err();
+    assert_eq!(status.code(), Code::OutOfRange, "{status:?}");
+
+    let mut client = message_size.apply(
+        Client::new(serve(MessageSize::new()).await?),
+        Client::max_decoding_message_size,
+        Client::max_encoding_message_size,
+    );
+    let status = client.get_feature(point()).await.unwrap_err();
+    assert_eq!(status.code(), Code::OutOfRange, "{status:?}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn queues_streams_over_the_limit() -> anyhow::Result<()> {
+    let mut client = Client::new(serve(MessageSize::new()).await?);
+    // Open until their request senders are dropped.
+    let mut chats = Vec::new();
+    for _ in 0..4 {
+        let (tx, rx) = tokio::sync::mpsc::channel::<lib::RouteNote>(1);
+        let requests = tokio_stream::wrappers::ReceiverStream::new(rx);
+        let chat = client.route_chat(requests).await?;
+        chats.push((tx, chat.into_inner()));
+    }
+    // The client waits for one of the 4 streams allowed on the connection to end.
+    let mut fifth = client.clone();
+    let mut call = tokio::spawn(async move { fifth.get_feature(point()).await });
+    let waiting = tokio::time::timeout(Duration::from_millis(200), &mut call).await;
+    assert!(waiting.is_err(), "5th stream served at once");
+    // Ends one chat cleanly, so that the server knows it's over when the client does.
+    let (tx, mut notes) = chats.pop().unwrap();
+    drop(tx);
+    while notes.try_next().await?.is_some() {}
+    let feature = tokio::time::timeout(Duration::from_secs(5), call).await???;
+    assert!(!feature.into_inner().name.is_empty());
+    Ok(())
+}